@@ -1,13 +1,21 @@
-use crate::cli::AddCommand;
-use crate::instance::InstanceMetadata;
+use crate::cli::{confirm_destructive, AddCommand};
+use crate::hashing::{HashAlgorithm, HashWithAlgorithm};
+use crate::instance::{InstanceMetadata, ModKind, ModMetadata};
 use crate::make_client;
-use anyhow::bail;
+use crate::mod_provider::ferium::{read_ferium_profile, FeriumSource};
+use crate::mod_provider::{ModProvider, PendingMod};
+use crate::output::{self, status, verbose};
+use crate::{ioutil, ContextExt};
+use anyhow::{bail, Context};
+use indicatif::MultiProgress;
 use reqwest::blocking::Client;
+use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::{fs, io};
 
 pub fn add_mod(command: AddCommand, cache_dir: PathBuf) -> anyhow::Result<()> {
-    let instance_path = Path::new(".");
-    let mut instance_metadata = InstanceMetadata::load(instance_path)?;
+    let instance_path = &command.instance;
+    let mut instance_metadata = InstanceMetadata::load(instance_path, command.metadata.as_deref())?;
 
     let Some(provider) = command
         .provider
@@ -19,25 +27,371 @@ pub fn add_mod(command: AddCommand, cache_dir: PathBuf) -> anyhow::Result<()> {
         );
     };
 
-    let added_mod = provider.add_mod(AddModArgs {
+    if let Some(file) = &command.file {
+        let added_mod = add_local_mod(&command, &instance_metadata, file)?;
+        merge_added_mods(&mut instance_metadata, vec![added_mod]);
+        instance_metadata.save(instance_path, command.metadata.as_deref())?;
+        return check_untracked_jars(&command, &instance_metadata);
+    }
+
+    if let Some(from_file) = &command.from_file {
+        let entries = read_mod_list(from_file, provider)?;
+        return add_mod_list(&command, &cache_dir, instance_metadata, &entries, from_file);
+    }
+    if let Some(ferium_profile) = &command.from_ferium {
+        let entries = read_ferium_entries(ferium_profile)?;
+        return add_mod_list(
+            &command,
+            &cache_dir,
+            instance_metadata,
+            &entries,
+            ferium_profile,
+        );
+    }
+
+    let Some(name) = &command.name else {
+        bail!("no mod name was given");
+    };
+    let client = make_client()?;
+    let added_mods = provider.add_mod(AddModArgs {
         command: &command,
-        client: &make_client()?,
+        name,
+        client: &client,
         cache_dir: &cache_dir,
         instance_path,
         instance_metadata: &instance_metadata,
+        pending_mods: &[],
     })?;
+    merge_added_mods(&mut instance_metadata, added_mods);
+    instance_metadata.save(instance_path, command.metadata.as_deref())?;
+    check_untracked_jars(&command, &instance_metadata)
+}
+
+// adds several mods at once, source is the file they were listed in
+pub fn add_mod_list(
+    command: &AddCommand,
+    cache_dir: &Path,
+    mut instance_metadata: InstanceMetadata,
+    entries: &[ModListEntry],
+    source: &Path,
+) -> anyhow::Result<()> {
+    let instance_path = &command.instance;
+    let client = make_client()?;
+
+    let mut failed = Vec::new();
+    // resolve one at a time since it may prompt, then download everything in parallel
+    let mut pending_mods: Vec<PendingMod> = Vec::new();
+    let mut pending_mod_lines = Vec::new();
+    for entry in entries {
+        let line = entry.label.as_str();
+        let mod_command = AddCommand {
+            version: entry.version.clone(),
+            from_file: None,
+            from_ferium: None,
+            ..command.clone()
+        };
+        status!("resolving {line}");
+        let result = entry.provider.resolve_mod(AddModArgs {
+            command: &mod_command,
+            name: &entry.name,
+            client: &client,
+            cache_dir,
+            instance_path,
+            instance_metadata: &instance_metadata,
+            pending_mods: &pending_mods,
+        });
+        match result {
+            Ok(resolved_mods) => {
+                for resolved_mod in resolved_mods {
+                    if pending_mods
+                        .iter()
+                        .any(|pending_mod| pending_mod.id == resolved_mod.id)
+                    {
+                        verbose!("{} is already being added", resolved_mod.name);
+                        continue;
+                    }
+                    pending_mods.push(resolved_mod);
+                    pending_mod_lines.push(line);
+                }
+            }
+            Err(err) => {
+                eprintln!("error: failed to add {line}: {err:#}");
+                failed.push(line);
+            }
+        }
+    }
+
+    let multi_progress = MultiProgress::new();
+    let active_bars = output::activate_bars(&multi_progress);
+    let downloads = pending_mods.into_iter().zip(pending_mod_lines).collect();
+    let results = ioutil::parallel_map(downloads, |(pending_mod, line)| {
+        (line, pending_mod.download(&client, Some(&multi_progress)))
+    });
+    drop(active_bars);
+    for (line, result) in results {
+        match result {
+            Ok(added_mod) => merge_added_mods(&mut instance_metadata, vec![added_mod]),
+            Err(err) => {
+                eprintln!("error: failed to add {line}: {err:#}");
+                if !failed.contains(&line) {
+                    failed.push(line);
+                }
+            }
+        }
+    }
+    instance_metadata.save(instance_path, command.metadata.as_deref())?;
+
+    let succeeded = entries
+        .iter()
+        .filter(|entry| !failed.contains(&entry.label.as_str()))
+        .count();
+    status!("added {succeeded} mods from {}", source.display());
+    if !failed.is_empty() {
+        bail!(
+            "failed to add {} of {} mods: {}",
+            failed.len(),
+            entries.len(),
+            failed.join(", ")
+        );
+    }
+
+    check_untracked_jars(command, &instance_metadata)
+}
+
+// jars dropped in by hand or left over from old versions, only deleted with --prune
+fn check_untracked_jars(
+    command: &AddCommand,
+    instance_metadata: &InstanceMetadata,
+) -> anyhow::Result<()> {
+    let Some(mods_folder) = ModKind::Mod.folder(instance_metadata.loader) else {
+        return Ok(());
+    };
+    let mods_path = command.instance.join(mods_folder);
+    let entries = match fs::read_dir(&mods_path) {
+        Ok(entries) => entries,
+        Err(err) if ioutil::is_not_found(&err) => return Ok(()),
+        Err(err) => return Err(err).with_path_context(&mods_path),
+    };
+
+    let mut untracked = Vec::new();
+    for entry in entries {
+        let entry = entry.with_path_context(&mods_path)?;
+        let Ok(file_name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if !file_name.ends_with(".jar") || !entry.path().is_file() {
+            continue;
+        }
+        let tracked = instance_metadata
+            .mods
+            .iter()
+            .any(|m| m.kind == ModKind::Mod && m.file_name == file_name);
+        if !tracked {
+            untracked.push(file_name);
+        }
+    }
+    if untracked.is_empty() {
+        return Ok(());
+    }
+    untracked.sort();
 
-    instance_metadata.mods.retain(|m| m.id != added_mod.id);
-    instance_metadata.mods.push(added_mod);
-    instance_metadata.save(instance_path)?;
+    if !command.prune {
+        eprintln!(
+            "warning: {} jars in {} aren't tracked by this instance, pass --prune to remove them: {}",
+            untracked.len(),
+            mods_path.display(),
+            untracked.join(", ")
+        );
+        return Ok(());
+    }
+
+    eprintln!(
+        "these jars in {} aren't tracked by this instance:",
+        mods_path.display()
+    );
+    for file_name in &untracked {
+        eprintln!("  {file_name}");
+    }
+    if !confirm_destructive("remove them?", command.yes)? {
+        return Ok(());
+    }
+    for file_name in &untracked {
+        let path = mods_path.join(file_name);
+        fs::remove_file(&path).with_path_context(&path)?;
+    }
+    status!(
+        "removed {} untracked jars from {}",
+        untracked.len(),
+        mods_path.display()
+    );
 
     Ok(())
 }
 
+#[derive(Debug, Clone)]
+pub struct ModListEntry {
+    // what the mod is called in messages, e.g. the line of the mod list
+    label: String,
+    name: String,
+    version: Option<String>,
+    provider: ModProvider,
+}
+
+fn read_mod_list(path: &Path, provider: ModProvider) -> anyhow::Result<Vec<ModListEntry>> {
+    let mod_list = fs::read_to_string(path).with_path_context(path)?;
+    let mut entries = Vec::new();
+    for line in mod_list.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        entries.push(ModListEntry::parse(line, provider));
+    }
+    Ok(entries)
+}
+
+impl ModListEntry {
+    pub fn new(name: &str, version: Option<String>, provider: ModProvider) -> ModListEntry {
+        let label = match &version {
+            Some(version) => format!("{name}@{version}"),
+            None => name.to_owned(),
+        };
+        ModListEntry {
+            label,
+            name: name.to_owned(),
+            version,
+            provider,
+        }
+    }
+
+    // a name, URL or name@version
+    pub fn parse(str: &str, provider: ModProvider) -> ModListEntry {
+        // urls may contain an @, only split plain names
+        let (name, version) = match str.rsplit_once('@') {
+            Some((name, version)) if !str.contains("://") => (name, Some(version.to_owned())),
+            _ => (str, None),
+        };
+        ModListEntry {
+            label: str.to_owned(),
+            name: name.to_owned(),
+            version,
+            provider,
+        }
+    }
+}
+
+// works offline, the jar is hashed so that a later install can tell if it was changed
+fn add_local_mod(
+    command: &AddCommand,
+    instance_metadata: &InstanceMetadata,
+    path: &Path,
+) -> anyhow::Result<ModMetadata> {
+    let loader = instance_metadata.loader;
+    let Some(mods_folder) = ModKind::Mod.folder(loader) else {
+        bail!("cannot install mods on loader '{loader}'");
+    };
+    let Some(file_name) = path.file_name().and_then(|file_name| file_name.to_str()) else {
+        bail!("{} is not a file", path.display());
+    };
+    ioutil::validate_jar(path)?;
+
+    let algorithm = HashAlgorithm::Sha512;
+    let mut digest = algorithm.create_hasher();
+    io::copy(&mut File::open(path).with_path_context(path)?, &mut digest)
+        .with_path_context(path)?;
+    let hash = HashWithAlgorithm {
+        algorithm,
+        hash: digest.finalize(),
+    };
+
+    for m in &instance_metadata.mods {
+        if m.kind != ModKind::Mod || m.file_name != file_name {
+            continue;
+        }
+        if m.provider != ModProvider::Local {
+            bail!(
+                "mod conflicts with existing mod {} ({}), which also has the filename '{file_name}'",
+                m.id,
+                m.name
+            );
+        }
+        let same_hash =
+            matches!(m.hash.algorithm, HashAlgorithm::Sha512) && m.hash.hash == hash.hash;
+        if same_hash && !command.reinstall {
+            bail!("mod is already up-to-date");
+        }
+    }
+
+    let mods_path = command.instance.join(mods_folder);
+    fs::create_dir_all(&mods_path).with_path_context(&mods_path)?;
+    let mod_path = mods_path.join(file_name);
+    // the jar may already be in the mods folder, and is only being tracked now
+    let already_in_place = fs::canonicalize(path)
+        .ok()
+        .is_some_and(|path| fs::canonicalize(&mod_path).is_ok_and(|mod_path| mod_path == path));
+    if !already_in_place {
+        fs::copy(path, &mod_path)
+            .with_context(|| format!("copying {} to {}", path.display(), mod_path.display()))?;
+    }
+    status!("added {file_name}");
+
+    let name = file_name.strip_suffix(".jar").unwrap_or(file_name);
+    Ok(ModMetadata {
+        id: file_name.to_owned(),
+        name: name.to_owned(),
+        file_name: file_name.to_owned(),
+        hash,
+        version: None,
+        provider: ModProvider::Local,
+        kind: ModKind::Mod,
+        folder: None,
+    })
+}
+
+// mods from sources we can't install from are skipped rather than failing the import
+fn read_ferium_entries(path: &Path) -> anyhow::Result<Vec<ModListEntry>> {
+    let mut entries = Vec::new();
+    for ferium_mod in read_ferium_profile(path)? {
+        match ferium_mod.source {
+            FeriumSource::Modrinth {
+                project_id,
+                version_id,
+            } => entries.push(ModListEntry {
+                label: ferium_mod.name,
+                name: project_id,
+                version: version_id,
+                provider: ModProvider::Modrinth,
+            }),
+            FeriumSource::GitHub { repo } => entries.push(ModListEntry {
+                label: ferium_mod.name,
+                name: repo,
+                version: None,
+                provider: ModProvider::GitHub,
+            }),
+            FeriumSource::Unsupported(source) => eprintln!(
+                "warning: skipping {}, mods from {source} can't be installed",
+                ferium_mod.name
+            ),
+        }
+    }
+    Ok(entries)
+}
+
+fn merge_added_mods(instance_metadata: &mut InstanceMetadata, added_mods: Vec<ModMetadata>) {
+    for added_mod in added_mods {
+        instance_metadata.mods.retain(|m| m.id != added_mod.id);
+        instance_metadata.mods.push(added_mod);
+    }
+}
+
 pub struct AddModArgs<'a> {
     pub command: &'a AddCommand,
+    // the mod to add, which may differ from the command's when adding dependencies or from a file
+    pub name: &'a str,
     pub client: &'a Client,
     pub cache_dir: &'a Path,
     pub instance_path: &'a Path,
     pub instance_metadata: &'a InstanceMetadata,
+    // mods that will be added along with this one, when adding several at once
+    pub pending_mods: &'a [PendingMod],
 }
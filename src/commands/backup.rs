@@ -0,0 +1,220 @@
+use crate::cli::BackupCommand;
+use crate::instance::{InstanceMetadata, ModKind, INSTANCE_METADATA_FILE};
+use crate::mod_loader::ModLoader;
+use crate::output::{status, verbose};
+use crate::properties::get_level_name;
+use crate::ContextExt;
+use anyhow::bail;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use time::OffsetDateTime;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+const SESSION_LOCK_FILENAME: &str = "session.lock";
+// bukkit based servers keep each dimension in its own folder next to the world
+pub const DIMENSION_FOLDER_SUFFIXES: &[&str] = &["", "_nether", "_the_end"];
+
+pub fn backup_instance(command: BackupCommand) -> anyhow::Result<()> {
+    let instance_path = &command.instance;
+    let instance_metadata = InstanceMetadata::load(instance_path, command.metadata.as_deref())?;
+
+    let level_name = get_level_name(instance_path)?;
+
+    let world_dirs: Vec<String> = if instance_metadata.loader == ModLoader::Bedrock {
+        vec![format!("worlds/{level_name}")]
+    } else {
+        DIMENSION_FOLDER_SUFFIXES
+            .iter()
+            .map(|suffix| format!("{level_name}{suffix}"))
+            .collect()
+    };
+    let world_dirs: Vec<_> = world_dirs
+        .into_iter()
+        .filter(|world_dir| instance_path.join(world_dir).is_dir())
+        .collect();
+    if world_dirs.is_empty() {
+        bail!(
+            "no world named {level_name} was found in {}, has the server been started yet?",
+            instance_path.display()
+        );
+    }
+
+    if !command.force {
+        for world_dir in &world_dirs {
+            let lock_path = instance_path.join(world_dir).join(SESSION_LOCK_FILENAME);
+            if is_session_locked(&lock_path)? {
+                bail!(
+                    "{} is locked, stop the server before backing up or pass --force to back up anyway",
+                    lock_path.display()
+                );
+            }
+        }
+    }
+
+    let mut entries = world_dirs;
+    if !command.world_only {
+        entries.push("server.properties".to_owned());
+        if command.metadata.is_none() {
+            entries.push(INSTANCE_METADATA_FILE.to_owned());
+        }
+    }
+    if command.include_mods {
+        for kind in [ModKind::Mod, ModKind::ResourcePack, ModKind::Shader] {
+            if let Some(folder) = kind.folder(instance_metadata.loader) {
+                if !entries.iter().any(|entry| entry == folder) {
+                    entries.push(folder.to_owned());
+                }
+            }
+        }
+    }
+
+    entries.retain(|entry| instance_path.join(entry).exists());
+
+    let output_path = match command.output.clone() {
+        Some(output_path) => output_path,
+        None => {
+            let backups_path = instance_path.join("backups");
+            fs::create_dir_all(&backups_path).with_path_context(&backups_path)?;
+            backups_path.join(format!("{level_name}-{}.zip", timestamp()))
+        }
+    };
+    let output_file = File::create(&output_path).with_path_context(&output_path)?;
+    let mut zip = ZipWriter::new(output_file);
+    let mut file_count = 0;
+    for entry in &entries {
+        let entry_path = instance_path.join(entry);
+        if entry_path.is_dir() {
+            add_directory(&mut zip, &entry_path, entry, &output_path, &mut file_count)?;
+        } else {
+            add_file(&mut zip, &entry_path, entry, &output_path)?;
+            file_count += 1;
+        }
+    }
+    // metadata kept outside the instance goes where it would be inside it, so the backup can be restored as is
+    if let Some(metadata_path) = command.metadata.as_deref().filter(|_| !command.world_only) {
+        add_file(
+            &mut zip,
+            metadata_path,
+            INSTANCE_METADATA_FILE,
+            &output_path,
+        )?;
+        entries.push(INSTANCE_METADATA_FILE.to_owned());
+        file_count += 1;
+    }
+    zip.finish().with_path_context(&output_path)?;
+
+    status!(
+        "backed up {file_count} files from {} to {}",
+        entries.join(", "),
+        output_path.display()
+    );
+
+    Ok(())
+}
+
+fn add_directory(
+    zip: &mut ZipWriter<File>,
+    dir: &Path,
+    name: &str,
+    output_path: &Path,
+    file_count: &mut usize,
+) -> anyhow::Result<()> {
+    let mut children = fs::read_dir(dir)
+        .with_path_context(dir)?
+        .collect::<io::Result<Vec<_>>>()
+        .with_path_context(dir)?;
+    children.sort_by_key(|child| child.file_name());
+
+    zip.add_directory(name, SimpleFileOptions::default())
+        .with_path_context(output_path)?;
+    for child in children {
+        let child_path = child.path();
+        let child_file_name = child.file_name();
+        // the running server keeps this locked, and it means nothing without the server
+        if child_file_name == SESSION_LOCK_FILENAME {
+            continue;
+        }
+        let child_name = format!("{name}/{}", child_file_name.to_string_lossy());
+        if child_path.is_dir() {
+            add_directory(zip, &child_path, &child_name, output_path, file_count)?;
+        } else {
+            add_file(zip, &child_path, &child_name, output_path)?;
+            *file_count += 1;
+        }
+    }
+
+    Ok(())
+}
+
+fn add_file(
+    zip: &mut ZipWriter<File>,
+    path: &Path,
+    name: &str,
+    output_path: &Path,
+) -> anyhow::Result<()> {
+    verbose!("adding {}", path.display());
+    let mut file = File::open(path).with_path_context(path)?;
+    let size = file.metadata().with_path_context(path)?.len();
+    let options = SimpleFileOptions::default().large_file(size >= u32::MAX as u64);
+    zip.start_file(name, options)
+        .with_path_context(output_path)?;
+    io::copy(&mut file, zip).with_path_context(path)?;
+    Ok(())
+}
+
+fn timestamp() -> String {
+    let now = OffsetDateTime::now_utc();
+    format!(
+        "{}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        now.year(),
+        u8::from(now.month()),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    )
+}
+
+// session.lock stays around after the server stops, it's only locked while the world is open
+fn is_session_locked(lock_path: &Path) -> anyhow::Result<bool> {
+    let file = match File::open(lock_path) {
+        Ok(file) => file,
+        Err(err) if crate::ioutil::is_not_found(&err) => return Ok(false),
+        Err(err) => return Err(err).with_path_context(lock_path),
+    };
+    is_file_locked(lock_path, &file)
+}
+
+// java takes fcntl locks, which the flock based File::try_lock can't see on linux
+#[cfg(target_os = "linux")]
+fn is_file_locked(path: &Path, file: &File) -> anyhow::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = file.metadata().with_path_context(path)?;
+    let dev = metadata.dev();
+    let major = ((dev >> 8) & 0xfff) as u32 | ((dev >> 32) as u32 & !0xfff);
+    let minor = (dev & 0xff) as u32 | ((dev >> 12) as u32 & !0xff);
+    // /proc/locks identifies files as major:minor:inode
+    let file_id = format!("{major:02x}:{minor:02x}:{}", metadata.ino());
+
+    let locks_path = Path::new("/proc/locks");
+    let locks = fs::read_to_string(locks_path).with_path_context(locks_path)?;
+    Ok(locks
+        .lines()
+        .any(|line| line.split_whitespace().any(|field| field == file_id)))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_file_locked(path: &Path, file: &File) -> anyhow::Result<bool> {
+    match file.try_lock_shared() {
+        Ok(()) => {
+            file.unlock().with_path_context(path)?;
+            Ok(false)
+        }
+        Err(std::fs::TryLockError::WouldBlock) => Ok(true),
+        Err(std::fs::TryLockError::Error(err)) => Err(err).with_path_context(path),
+    }
+}
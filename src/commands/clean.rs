@@ -0,0 +1,170 @@
+use crate::cli::{confirm_destructive, CleanCacheCommand};
+use crate::{ContextExt, DEFAULT_CONFIG_TEMPLATE_DIR};
+use indicatif::HumanBytes;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub fn clean_cache(command: CleanCacheCommand, cache_dir: PathBuf) -> anyhow::Result<()> {
+    let mut cache_files = Vec::new();
+    collect_cache_files(&cache_dir, true, &mut cache_files)?;
+
+    let total_size: u64 = cache_files.iter().map(|file| file.size).sum();
+    eprintln!(
+        "cache at {} contains {} files ({})",
+        cache_dir.display(),
+        cache_files.len(),
+        HumanBytes(total_size)
+    );
+
+    let cutoff = match (command.all, command.older_than) {
+        (true, _) => None,
+        (false, Some(days)) => Some(
+            SystemTime::now()
+                .checked_sub(Duration::from_secs(days.saturating_mul(SECONDS_PER_DAY)))
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+        ),
+        (false, None) => {
+            eprintln!("pass --older-than <days> or --all to remove cached files");
+            return Ok(());
+        }
+    };
+
+    let mut linked_files = HashSet::new();
+    for instance in &command.keep_linked_from {
+        collect_link_targets(instance, &mut linked_files)?;
+    }
+
+    cache_files.retain(|file| {
+        if command.keep_jars && file.path.extension().is_some_and(|ext| ext == "jar") {
+            return false;
+        }
+        if fs::canonicalize(&file.path).is_ok_and(|path| linked_files.contains(&path)) {
+            return false;
+        }
+        cutoff.is_none_or(|cutoff| file.modified < cutoff)
+    });
+    let removed_count = cache_files.len();
+    let removed_size: u64 = cache_files.iter().map(|file| file.size).sum();
+
+    if command.dry_run {
+        for file in &cache_files {
+            eprintln!("would remove {}", file.path.display());
+        }
+    } else if removed_count > 0 {
+        let removes_jars = cache_files
+            .iter()
+            .any(|file| file.path.extension().is_some_and(|ext| ext == "jar"));
+        // instances that aren't standalone link their jars from the cache
+        let prompt = if removes_jars {
+            format!(
+                "remove {removed_count} files ({})? instances linking to the removed jars will stop working, pass --keep-linked-from for the ones to keep",
+                HumanBytes(removed_size)
+            )
+        } else {
+            format!(
+                "remove {removed_count} files ({})?",
+                HumanBytes(removed_size)
+            )
+        };
+        if !confirm_destructive(&prompt, command.yes)? {
+            return Ok(());
+        }
+        for file in &cache_files {
+            fs::remove_file(&file.path).with_path_context(&file.path)?;
+        }
+    }
+
+    if !command.dry_run {
+        remove_empty_dirs(&cache_dir, true)?;
+    }
+
+    eprintln!(
+        "{} {removed_count} files ({})",
+        if command.dry_run {
+            "would remove"
+        } else {
+            "removed"
+        },
+        HumanBytes(removed_size)
+    );
+
+    Ok(())
+}
+
+// symlinks are collected rather than followed, so links into the cache aren't walked through
+fn collect_link_targets(dir: &Path, targets: &mut HashSet<PathBuf>) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir).with_path_context(dir)? {
+        let entry = entry.with_path_context(dir)?;
+        let path = entry.path();
+        let file_type = entry.file_type().with_path_context(&path)?;
+        if file_type.is_symlink() {
+            if let Ok(target) = fs::canonicalize(&path) {
+                targets.insert(target);
+            }
+        } else if file_type.is_dir() {
+            collect_link_targets(&path, targets)?;
+        }
+    }
+
+    Ok(())
+}
+
+struct CacheFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+fn collect_cache_files(
+    dir: &Path,
+    is_root: bool,
+    cache_files: &mut Vec<CacheFile>,
+) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir).with_path_context(dir)? {
+        let entry = entry.with_path_context(dir)?;
+        let path = entry.path();
+        // the config template is user configuration rather than a download
+        if is_root && entry.file_name() == DEFAULT_CONFIG_TEMPLATE_DIR {
+            continue;
+        }
+
+        let file_type = entry.file_type().with_path_context(&path)?;
+        if file_type.is_dir() {
+            collect_cache_files(&path, false, cache_files)?;
+        } else {
+            let metadata = entry.metadata().with_path_context(&path)?;
+            cache_files.push(CacheFile {
+                path,
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn remove_empty_dirs(dir: &Path, is_root: bool) -> anyhow::Result<bool> {
+    let mut empty = true;
+    for entry in fs::read_dir(dir).with_path_context(dir)? {
+        let entry = entry.with_path_context(dir)?;
+        let path = entry.path();
+        if entry.file_type().with_path_context(&path)?.is_dir() {
+            if is_root && entry.file_name() == DEFAULT_CONFIG_TEMPLATE_DIR {
+                empty = false;
+            } else if remove_empty_dirs(&path, false)? {
+                fs::remove_dir(&path).with_path_context(&path)?;
+            } else {
+                empty = false;
+            }
+        } else {
+            empty = false;
+        }
+    }
+
+    Ok(empty)
+}
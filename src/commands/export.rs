@@ -0,0 +1,127 @@
+use crate::cli::{ExportCommand, ExportFormat};
+use crate::instance::InstanceMetadata;
+use crate::mod_loader::ModLoader;
+use crate::output::status;
+use crate::{ContextExt, LINE_ENDING};
+use anyhow::bail;
+use clap::crate_name;
+use serde_json::json;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+// multimc and prism look for the game directory under this name inside the instance
+const MINECRAFT_DIR: &str = ".minecraft";
+
+pub fn export_instance(command: ExportCommand) -> anyhow::Result<()> {
+    let instance_metadata = InstanceMetadata::load(&command.instance, command.metadata.as_deref())?;
+
+    match command.format {
+        ExportFormat::Prism => export_prism(&command, &instance_metadata),
+    }
+}
+
+fn export_prism(
+    command: &ExportCommand,
+    instance_metadata: &InstanceMetadata,
+) -> anyhow::Result<()> {
+    let instance_path = &command.instance;
+    let mut components = vec![json!({
+        "uid": "net.minecraft",
+        "version": instance_metadata.minecraft_version,
+        "important": true,
+    })];
+    match instance_metadata.loader {
+        ModLoader::Vanilla => {}
+        ModLoader::Fabric => {
+            let Some(loader_version) = &instance_metadata.loader_version else {
+                bail!(
+                    "instance has no fabric loader version recorded, it was likely created by an older version of {}",
+                    crate_name!()
+                );
+            };
+            components.push(json!({
+                "uid": "net.fabricmc.intermediary",
+                "version": instance_metadata.minecraft_version,
+                "dependencyOnly": true,
+            }));
+            components.push(json!({
+                "uid": "net.fabricmc.fabric-loader",
+                "version": loader_version,
+            }));
+        }
+        loader @ (ModLoader::Paper | ModLoader::Spigot | ModLoader::Bedrock) => {
+            bail!("{loader} servers can't be exported to a client instance");
+        }
+    }
+    let mmc_pack = json!({
+        "components": components,
+        "formatVersion": 1,
+    });
+
+    let name = if instance_metadata.name.is_empty() {
+        instance_path
+            .canonicalize()
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "instance".to_owned())
+    } else {
+        instance_metadata.name.clone()
+    };
+    let instance_cfg = format!("InstanceType=OneSix{LINE_ENDING}name={name}{LINE_ENDING}");
+
+    let output_path = command
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{name}.zip")));
+    let output_file = File::create(&output_path).with_path_context(&output_path)?;
+    let mut zip = ZipWriter::new(output_file);
+    let options = SimpleFileOptions::default();
+
+    zip.start_file("instance.cfg", options)
+        .with_path_context(&output_path)?;
+    zip.write_all(instance_cfg.as_bytes())
+        .with_path_context(&output_path)?;
+    zip.start_file("mmc-pack.json", options)
+        .with_path_context(&output_path)?;
+    zip.write_all(serde_json::to_string_pretty(&mmc_pack)?.as_bytes())
+        .with_path_context(&output_path)?;
+
+    let mut exported_mods = 0;
+    for mod_metadata in &instance_metadata.mods {
+        let Some(folder) = mod_metadata.kind.folder(instance_metadata.loader) else {
+            continue;
+        };
+        let mod_path = instance_path.join(folder).join(&mod_metadata.file_name);
+        let mut mod_file = match File::open(&mod_path) {
+            Ok(mod_file) => mod_file,
+            Err(err) if crate::ioutil::is_not_found(&err) => {
+                eprintln!("warning: {} is missing, skipping it", mod_path.display());
+                continue;
+            }
+            Err(err) => return Err(err).with_path_context(&mod_path),
+        };
+        zip.start_file(
+            format!("{MINECRAFT_DIR}/{folder}/{}", mod_metadata.file_name),
+            options,
+        )
+        .with_path_context(&output_path)?;
+        io::copy(&mut mod_file, &mut zip).with_path_context(&mod_path)?;
+        exported_mods += 1;
+    }
+
+    zip.finish().with_path_context(&output_path)?;
+
+    status!(
+        "exported {name} with {exported_mods} mods to {}",
+        output_path.display()
+    );
+
+    Ok(())
+}
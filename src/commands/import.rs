@@ -0,0 +1,108 @@
+use crate::cli::ImportCommand;
+use crate::instance::{InstanceMetadata, LaunchMetadata};
+use crate::java::create_java_candidate_for_path;
+use crate::mod_loader::detect::detect_server;
+use crate::mod_loader::ModLoader;
+use crate::output::status;
+use anyhow::bail;
+
+pub fn import_instance(command: ImportCommand) -> anyhow::Result<()> {
+    let instance_path = &command.instance;
+    if !instance_path.is_dir() {
+        bail!("{} is not a directory", instance_path.display());
+    }
+    if InstanceMetadata::exists(instance_path, command.metadata.as_deref()) && !command.force {
+        bail!(
+            "{} is already an instance, use --force to replace its metadata",
+            instance_path.display()
+        );
+    }
+
+    let detected = detect_server(instance_path)?;
+    let Some(loader) = command
+        .loader
+        .or_else(|| detected.as_ref().map(|detected| detected.loader))
+    else {
+        bail!(
+            "could not detect a server in {}, pass --loader and --version",
+            instance_path.display()
+        );
+    };
+    // what was detected doesn't apply if the user says it's a different loader
+    let detected = detected.filter(|detected| detected.loader == loader);
+    if detected.is_some() {
+        status!("detected {loader} server");
+    }
+
+    let Some(minecraft_version) = command.version.clone().or_else(|| {
+        detected
+            .as_ref()
+            .and_then(|detected| detected.minecraft_version.clone())
+    }) else {
+        bail!("could not detect the Minecraft version of the server, pass --version");
+    };
+    let loader_version = detected
+        .as_ref()
+        .and_then(|detected| detected.loader_version.clone());
+
+    let launch = match &command.custom_java_exe {
+        Some(_) if loader == ModLoader::Bedrock => {
+            bail!("--custom-java-exe cannot be used with bedrock servers");
+        }
+        Some(java_exe) => {
+            let java_candidate = create_java_candidate_for_path(java_exe.clone(), &mut None)?;
+            status!("using java executable {java_candidate}");
+            let server_jar_name = detected
+                .as_ref()
+                .and_then(|detected| detected.server_jar_name.clone())
+                .filter(|name| !loader.launch_args(None).contains(name));
+            if let Some(server_jar_name) = &server_jar_name {
+                if !loader.supports_server_jar_name() {
+                    eprintln!("warning: the server is started from {server_jar_name}, but regenerated {loader} run scripts can't use a custom jar name");
+                }
+            }
+            Some(LaunchMetadata {
+                java_path: java_candidate.path,
+                memory: None,
+                jvm_args: Vec::new(),
+                server_jar_name: server_jar_name.filter(|_| loader.supports_server_jar_name()),
+                respect_java_opts: false,
+            })
+        }
+        None => None,
+    };
+
+    let name = match &command.name {
+        Some(name) => name.clone(),
+        None => instance_path
+            .canonicalize()
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_default(),
+    };
+
+    let instance_metadata = InstanceMetadata::new(
+        &name,
+        loader,
+        loader_version.clone(),
+        &minecraft_version,
+        launch,
+    );
+    instance_metadata.save(instance_path, command.metadata.as_deref())?;
+
+    match loader_version {
+        Some(loader_version) => status!(
+            "imported {loader} {loader_version} server for minecraft {minecraft_version} in {}",
+            instance_path.display()
+        ),
+        None => status!(
+            "imported {loader} server for minecraft {minecraft_version} in {}",
+            instance_path.display()
+        ),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use crate::cli::JavaListCommand;
+use crate::java::{find_java_candidates, JavaCandidate};
+use crate::output::status;
+use serde::Serialize;
+use std::path::PathBuf;
+
+#[derive(Debug, Serialize)]
+struct JavaListEntry {
+    path: PathBuf,
+    version: String,
+    major: u32,
+    vm: Option<String>,
+    arch: Option<String>,
+    vendor: Option<String>,
+}
+
+impl JavaListEntry {
+    fn new(java_candidate: &JavaCandidate) -> Self {
+        JavaListEntry {
+            path: java_candidate.path.clone(),
+            version: java_candidate.version.to_string(),
+            major: java_candidate.version.major,
+            vm: java_candidate.vm.map(|vm| vm.to_string()),
+            arch: java_candidate.arch.map(|arch| arch.to_string()),
+            vendor: java_candidate.vendor.clone(),
+        }
+    }
+}
+
+pub fn list_java(command: JavaListCommand) -> anyhow::Result<()> {
+    let java_candidates = find_java_candidates()?;
+
+    if command.json {
+        let entries: Vec<_> = java_candidates.iter().map(JavaListEntry::new).collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    if java_candidates.is_empty() {
+        eprintln!("no java installs were found");
+        return Ok(());
+    }
+    for java_candidate in &java_candidates {
+        println!("{}", java_candidate.path.display());
+        println!("   version: {}", java_candidate.version);
+        if let Some(vm) = java_candidate.vm {
+            println!("   vm: {vm}");
+        }
+        if let Some(arch) = java_candidate.arch {
+            println!("   arch: {arch}");
+        }
+        if let Some(vendor) = &java_candidate.vendor {
+            println!("   vendor: {vendor}");
+        }
+    }
+    status!("found {} java installs", java_candidates.len());
+
+    Ok(())
+}
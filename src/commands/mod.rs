@@ -1,2 +1,9 @@
 pub mod add;
+pub mod backup;
+pub mod clean;
+pub mod export;
+pub mod import;
+pub mod java;
 pub mod new;
+pub mod prefetch;
+pub mod regen;
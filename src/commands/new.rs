@@ -1,43 +1,866 @@
-use crate::cli::NewCommand;
-use crate::instance::InstanceMetadata;
-use crate::java::{create_java_candidate_for_path, find_java_candidates, JavaCandidate};
+use crate::cli::{confirm_destructive, AddCommand, LoaderChoice, NewCommand};
+use crate::commands::add::add_mod_list;
+use crate::commands::backup::DIMENSION_FOLDER_SUFFIXES;
+use crate::install_manifest::write_install_manifest;
+use crate::instance::{InstanceMetadata, LaunchMetadata};
+use crate::java::{
+    create_java_candidate_for_path, find_java_candidates, verify_java, JavaCandidate,
+};
+use crate::memory::JvmMemory;
+use crate::mod_loader::bedrock::install_bedrock;
+use crate::mod_loader::detect::{detect_server, DetectedServer};
+use crate::mod_loader::vanilla::{agree_to_eula, check_eula_can_be_asked};
+use crate::mod_loader::{LoaderChannel, ModLoader};
+use crate::mod_provider::modpack::CurseForgeModpack;
+use crate::mod_provider::modrinth::VersionChannel;
 use crate::mojang::{Manifest, ManifestVersion, Version};
-use crate::{cli, ioutil, make_client, ContextExt, RUN_SERVER_FILENAME};
+use crate::output::{status, verbose};
+use crate::properties::{get_level_name, get_property, set_properties};
+use crate::template::substitute_defines;
+use crate::{
+    cli, ioutil, make_client, ContextExt, DEFAULT_CONFIG_TEMPLATE_DIR, LINE_ENDING,
+    RUN_SERVER_FILENAME,
+};
 use anyhow::{anyhow, bail, Context};
 use reqwest::blocking::Client;
 use std::borrow::Cow;
 use std::cmp::Ordering;
+use std::collections::BTreeSet;
 use std::fs;
 use std::fs::File;
-use std::io::Write;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use time::OffsetDateTime;
 
-pub fn make_new_instance(command: NewCommand, cache_dir: PathBuf) -> anyhow::Result<()> {
-    let instance_path = PathBuf::from(&command.name);
-    if instance_path.exists() {
-        bail!("an instance with that name already exists");
+const DEFAULT_SERVER_PORT: u16 = 25565;
+// left unquoted on unix so that it can contain several flags
+#[cfg(windows)]
+const JAVA_OPTS_VAR: &str = "%JAVA_OPTS%";
+#[cfg(not(windows))]
+const JAVA_OPTS_VAR: &str = "$JAVA_OPTS";
+#[cfg(windows)]
+const JDK_JAVA_OPTIONS_NOTE: &str =
+    "@rem java also picks up flags from the JDK_JAVA_OPTIONS environment variable";
+#[cfg(not(windows))]
+const JDK_JAVA_OPTIONS_NOTE: &str =
+    "# java also picks up flags from the JDK_JAVA_OPTIONS environment variable";
+// the script refers to the server files by relative path, so it has to run from its own folder
+#[cfg(windows)]
+const CD_TO_SCRIPT_DIR: &str = "cd /d \"%~dp0\"";
+#[cfg(not(windows))]
+const CD_TO_SCRIPT_DIR: &str = "cd \"$(dirname \"$0\")\"";
+#[cfg(windows)]
+const SHELL: &[&str] = &["cmd", "/C"];
+#[cfg(not(windows))]
+const SHELL: &[&str] = &["sh", "-c"];
+const HOOK_ENV_PREFIX: &str = "GENERATE_MCSERVER_";
+const SERVER_ICON_FILENAME: &str = "server-icon.png";
+const SERVER_ICON_SIZE: u32 = 64;
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+// java versions that eclipse-temurin publishes jre images for
+const TEMURIN_JRE_VERSIONS: &[u32] = &[8, 11, 17, 21, 25];
+
+pub fn make_new_instance(mut command: NewCommand, cache_dir: PathBuf) -> anyhow::Result<()> {
+    let started_at = OffsetDateTime::now_utc();
+    if command.manifest.is_some() {
+        ioutil::start_recording_downloads();
+    }
+
+    let instance_path = command
+        .dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(command.name()));
+
+    // --repair takes everything that was recorded from the existing instance
+    let existing_metadata = if command.repair {
+        let existing_metadata = InstanceMetadata::load(&instance_path, command.metadata.as_deref()).with_context(|| {
+            format!(
+                "{} is not an instance that can be repaired, use --force to generate the server into it instead",
+                instance_path.display()
+            )
+        })?;
+        let prompt = format!(
+            "repair {}? its server jars and run script will be replaced",
+            instance_path.display()
+        );
+        if !confirm_destructive(&prompt, command.yes)? {
+            bail!("aborted");
+        }
+        apply_existing_metadata(&mut command, &existing_metadata);
+        Some(existing_metadata)
+    } else {
+        None
+    };
+
+    let modpack = match &command.modpack {
+        Some(modpack_path) => Some(CurseForgeModpack::open(
+            modpack_path,
+            command.curseforge_api_key.as_deref(),
+        )?),
+        None => None,
+    };
+    if let Some(modpack) = &modpack {
+        let (loader, loader_version) = modpack.loader()?;
+        if let Some(LoaderChoice::Loader(loader_choice)) = command.loader_choice {
+            if loader_choice != loader {
+                bail!("the modpack uses {loader}, but --loader {loader_choice} was passed");
+            }
+        }
+        command.version = Some(modpack.minecraft_version().to_owned());
+        command.loader = loader;
+        command.fabric_loader_version = loader_version;
+    } else if let Some(existing_metadata) = &existing_metadata {
+        command.loader = existing_metadata.loader;
+    } else {
+        command.loader = match command
+            .loader_choice
+            .unwrap_or(LoaderChoice::Loader(ModLoader::Vanilla))
+        {
+            LoaderChoice::Loader(loader) => loader,
+            LoaderChoice::Auto => {
+                let Some(detected) = detect_server(&instance_path)? else {
+                    bail!(
+                        "could not detect the loader of an existing server in {}, pass --loader",
+                        instance_path.display()
+                    );
+                };
+                status!(
+                    "detected {} server in {}",
+                    detected.loader,
+                    instance_path.display()
+                );
+                apply_detected_server(&mut command, &detected);
+                detected.loader
+            }
+        };
+    }
+    command.validate_loader()?;
+    if command.allow_experimental {
+        command.loader_channel = LoaderChannel::Latest;
+    }
+    // the eula is asked about after downloading, don't download everything only to fail there.
+    // bedrock asks even with --jar-only, since its download needs it
+    let asks_eula = command.loader == ModLoader::Bedrock || !command.jar_only;
+    if asks_eula && !command.repair {
+        check_eula_can_be_asked(&command)?;
+    }
+    if let Some(icon) = &command.icon {
+        check_server_icon(icon)?;
+    }
+
+    // the default is in the cache directory, which isn't known until the arguments are parsed
+    let uses_default_template = command.config_template.is_none();
+    let config_template = command
+        .config_template
+        .clone()
+        .unwrap_or_else(|| cache_dir.join(DEFAULT_CONFIG_TEMPLATE_DIR));
+
+    // check the template before anything is downloaded
+    if command.print_template_hash || command.template_hash.is_some() {
+        if uses_default_template {
+            create_default_config_template(&config_template)?;
+        }
+        let template_hash = ioutil::hash_template(&config_template)?;
+        if command.print_template_hash {
+            println!("{template_hash}");
+            return Ok(());
+        }
+        if let Some(expected_hash) = &command.template_hash {
+            if template_hash.inner != expected_hash.inner {
+                bail!(
+                    "config template {} has hash {template_hash}, expected {expected_hash}",
+                    config_template.display()
+                );
+            }
+        }
+    }
+
+    // an instance being repaired or detected is expected to already have files in it
+    let expects_existing = command.repair || command.loader_choice == Some(LoaderChoice::Auto);
+    if !expects_existing && instance_path.is_dir() {
+        let is_empty = fs::read_dir(&instance_path)
+            .with_path_context(&instance_path)?
+            .next()
+            .is_none();
+        if !is_empty && !command.force {
+            bail!(
+                "{} already exists and is not empty, use --force to generate the server into it anyway",
+                instance_path.display()
+            );
+        }
+        if !is_empty {
+            let prompt = format!(
+                "{} is not empty, overwrite any conflicting files in it?",
+                instance_path.display()
+            );
+            if !confirm_destructive(&prompt, command.yes)? {
+                bail!("aborted");
+            }
+        }
+    } else if !expects_existing && instance_path.exists() {
+        bail!(
+            "{} already exists and is not a directory",
+            instance_path.display()
+        );
     }
 
     let client = make_client()?;
+    // symlinks into the cache can't be followed from inside the docker build context
+    if command.docker {
+        command.standalone = true;
+    }
+
+    let (version, installed, java_candidate) = if command.loader == ModLoader::Bedrock {
+        let version = install_bedrock(&command, &client, &cache_dir, &instance_path)?;
+        (version, None, None)
+    } else {
+        let (version, installed, java_candidate) =
+            install_java_server(&command, &client, &cache_dir, &instance_path)?;
+        (version, Some(installed), Some(java_candidate))
+    };
+    report_version(&command, &version)?;
+    // bedrock can't be downloaded without accepting the eula
+    let (launch, loader_version, installer_version, eula_accepted) = match installed {
+        Some(installed) => (
+            Some(installed.launch),
+            installed.loader_version,
+            installed.installer_version,
+            installed.eula_accepted,
+        ),
+        None => (None, None, None, true),
+    };
+
+    if let Some(existing_metadata) = existing_metadata {
+        finish_repair(
+            &command,
+            &instance_path,
+            existing_metadata,
+            launch,
+            loader_version.clone(),
+            installer_version,
+            eula_accepted,
+        )?;
+        write_manifest(
+            &command,
+            started_at,
+            &version,
+            loader_version.as_deref(),
+            java_candidate.as_ref(),
+        )?;
+        return run_after_install_hook(
+            &command,
+            &instance_path,
+            &version,
+            loader_version.as_deref(),
+        );
+    }
+
+    // the default template is for java servers, bedrock servers come with their own server.properties
+    if command.loader != ModLoader::Bedrock || !uses_default_template {
+        if uses_default_template {
+            create_default_config_template(&config_template)?;
+        }
+        let template_files = ioutil::copy_template(&config_template, &instance_path)?;
+        if !command.defines.is_empty() || command.strict_defines {
+            substitute_defines(&template_files, &command.defines, command.strict_defines)?;
+        }
+    }
+    if let Some(icon) = &command.icon {
+        let icon_path = instance_path.join(SERVER_ICON_FILENAME);
+        fs::copy(icon, &icon_path)
+            .with_context(|| format!("copying {} to {}", icon.display(), icon_path.display()))?;
+    }
+
+    if let Some(modpack) = &modpack {
+        modpack.install(&client, &instance_path)?;
+    }
+    write_manifest(
+        &command,
+        started_at,
+        &version,
+        loader_version.as_deref(),
+        java_candidate.as_ref(),
+    )?;
+
+    let mut properties = Vec::new();
+    if let Some(port) = command.port {
+        properties.push(("server-port".to_owned(), port.to_string()));
+        if command.loader != ModLoader::Bedrock {
+            properties.push(("query.port".to_owned(), port.to_string()));
+        }
+    }
+    if let Some(level_name) = &command.level_name {
+        properties.push(("level-name".to_owned(), level_name.clone()));
+    }
+    if let Some(seed) = &command.seed {
+        properties.push(("level-seed".to_owned(), seed.clone()));
+    }
+    properties.extend(command.properties.iter().cloned());
+    set_properties(&instance_path.join("server.properties"), &properties)?;
+    let world_storage = match &command.world_storage {
+        Some(world_storage) => Some(link_world_storage(&command, &instance_path, world_storage)?),
+        None => None,
+    };
+
+    let mut instance_metadata = InstanceMetadata::new(
+        command.name(),
+        command.loader,
+        loader_version.clone(),
+        &version,
+        launch,
+    );
+    instance_metadata.installer_version = installer_version;
+    instance_metadata.eula_accepted = eula_accepted;
+    instance_metadata.world_storage = world_storage;
+    // re-running an interrupted install shouldn't forget the mods the first run recorded
+    if InstanceMetadata::exists(&instance_path, command.metadata.as_deref()) {
+        match InstanceMetadata::load(&instance_path, command.metadata.as_deref()) {
+            Ok(existing_metadata) if existing_metadata.loader == command.loader => {
+                instance_metadata.mods = existing_metadata.mods;
+            }
+            Ok(_) => verbose!(
+                "not keeping the mods of the existing instance, it uses a different loader"
+            ),
+            Err(err) => verbose!("not keeping the mods of the existing instance: {err:#}"),
+        }
+    }
+    instance_metadata.save(&instance_path, command.metadata.as_deref())?;
+    if !command.config_mods.is_empty() {
+        add_config_mods(&command, &cache_dir, &instance_path)?;
+    }
+
+    if command.verify_java {
+        if let Some(launch) = &instance_metadata.launch {
+            status!("verifying that java can run the server");
+            verify_java(
+                &launch.java_path,
+                &launch.all_jvm_args(),
+                &command
+                    .loader
+                    .launch_args(launch.server_jar_name.as_deref()),
+                &instance_path,
+            )
+            .with_context(|| {
+                format!(
+                    "the server was created in {} but could not be run with the selected java",
+                    instance_path.display()
+                )
+            })?;
+        }
+    }
+
+    if command.docker {
+        if let (Some(launch), Some(java_candidate)) = (&instance_metadata.launch, &java_candidate) {
+            write_docker_files(
+                &instance_path,
+                &command,
+                launch,
+                java_candidate.version.major,
+            )?;
+        }
+    }
+
+    run_after_install_hook(
+        &command,
+        &instance_path,
+        &version,
+        loader_version.as_deref(),
+    )?;
+
+    print_summary(
+        &command,
+        &instance_path,
+        &version,
+        loader_version.as_deref(),
+        java_candidate.as_ref(),
+    );
+
+    Ok(())
+}
+
+fn run_after_install_hook(
+    command: &NewCommand,
+    instance_path: &Path,
+    version: &str,
+    loader_version: Option<&str>,
+) -> anyhow::Result<()> {
+    let Some(hook) = &command.after_install else {
+        return Ok(());
+    };
+
+    status!("running after install command");
+    let instance_path = fs::canonicalize(instance_path).with_path_context(instance_path)?;
+    let mut hook_command = Command::new(SHELL[0]);
+    hook_command
+        .args(&SHELL[1..])
+        .arg(hook)
+        .current_dir(&instance_path)
+        .env(format!("{HOOK_ENV_PREFIX}INSTANCE"), &instance_path)
+        .env(format!("{HOOK_ENV_PREFIX}MINECRAFT_VERSION"), version)
+        .env(
+            format!("{HOOK_ENV_PREFIX}LOADER"),
+            command.loader.to_string(),
+        );
+    if let Some(loader_version) = loader_version {
+        hook_command.env(format!("{HOOK_ENV_PREFIX}LOADER_VERSION"), loader_version);
+    }
+    let exit_status = hook_command
+        .status()
+        .with_context(|| format!("running after install command {hook}"))?;
+    if !exit_status.success() {
+        if command.ignore_hook_errors {
+            eprintln!("warning: after install command failed with {exit_status}");
+        } else {
+            bail!(
+                "the server was created in {} but the after install command failed with {exit_status}",
+                instance_path.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn write_manifest(
+    command: &NewCommand,
+    started_at: OffsetDateTime,
+    version: &str,
+    loader_version: Option<&str>,
+    java_candidate: Option<&JavaCandidate>,
+) -> anyhow::Result<()> {
+    let Some(manifest_path) = &command.manifest else {
+        return Ok(());
+    };
+    write_install_manifest(
+        manifest_path,
+        started_at,
+        version,
+        command.loader,
+        loader_version,
+        java_candidate,
+    )?;
+    verbose!("wrote install manifest to {}", manifest_path.display());
+    Ok(())
+}
+
+// the server creates the world through the links, returns the canonical storage path
+fn link_world_storage(
+    command: &NewCommand,
+    instance_path: &Path,
+    world_storage: &Path,
+) -> anyhow::Result<PathBuf> {
+    let level_name = get_level_name(instance_path)?;
+    for folder in world_folders(command.loader, &level_name) {
+        let target = world_storage.join(&folder);
+        let link_name = instance_path.join(&folder);
+        verbose!("linking {} to {}", link_name.display(), target.display());
+        ioutil::link_directory(&target, &link_name)
+            .with_context(|| format!("linking {} to {}", link_name.display(), target.display()))?;
+    }
+    status!("storing the world in {}", world_storage.display());
+    fs::canonicalize(world_storage).with_path_context(world_storage)
+}
+
+// the folders the server keeps the world in, relative to the instance
+fn world_folders(loader: ModLoader, level_name: &str) -> Vec<String> {
+    let suffixes = if matches!(loader, ModLoader::Spigot | ModLoader::Paper) {
+        DIMENSION_FOLDER_SUFFIXES
+    } else {
+        &[""]
+    };
+    suffixes
+        .iter()
+        .map(|suffix| format!("{level_name}{suffix}"))
+        .collect()
+}
+
+// the equivalent of add --from-file with the mods listed in --config
+fn add_config_mods(
+    command: &NewCommand,
+    cache_dir: &Path,
+    instance_path: &Path,
+) -> anyhow::Result<()> {
+    let Some(provider) = command.loader.default_mod_provider() else {
+        bail!("cannot install mods on loader '{}'", command.loader);
+    };
+    let entries: Vec<_> = command
+        .config_mods
+        .iter()
+        .map(|config_mod| config_mod.to_entry(provider))
+        .collect();
+    let add_command = AddCommand {
+        name: None,
+        version: None,
+        from_file: None,
+        from_ferium: None,
+        file: None,
+        provider: None,
+        skip_version_check: false,
+        include_snapshots: false,
+        snapshot_as_release: false,
+        strict_loader: false,
+        force_search: false,
+        reinstall: false,
+        no_deps: false,
+        allow_archived: false,
+        channel: VersionChannel::Release,
+        show_changelog: false,
+        prune: false,
+        yes: command.yes,
+        instance: instance_path.to_owned(),
+        metadata: command.metadata.clone(),
+    };
+    let instance_metadata = InstanceMetadata::load(instance_path, command.metadata.as_deref())?;
+    let config = command.config.as_deref().unwrap_or(Path::new("config"));
+    add_mod_list(&add_command, cache_dir, instance_metadata, &entries, config)
+}
+
+// keeps the detected server's versions rather than installing the latest over it
+fn apply_detected_server(command: &mut NewCommand, detected: &DetectedServer) {
+    if command.version.is_none() {
+        command.version = detected.minecraft_version.clone();
+    }
+    match detected.loader {
+        ModLoader::Fabric => {
+            if command.fabric_loader_version.is_none() {
+                command.fabric_loader_version = detected.loader_version.clone();
+            }
+        }
+        ModLoader::Paper => {
+            if command.paper_build.is_none() {
+                command.paper_build = detected
+                    .loader_version
+                    .as_deref()
+                    .and_then(|build| build.parse().ok());
+            }
+        }
+        ModLoader::Vanilla | ModLoader::Spigot | ModLoader::Bedrock => {}
+    }
+}
 
-    eprintln!("fetching minecraft versions");
-    let manifest = Manifest::download(&client, &cache_dir.join("version_manifest.json"))?;
+fn apply_existing_metadata(command: &mut NewCommand, existing_metadata: &InstanceMetadata) {
+    command.version = Some(existing_metadata.minecraft_version.clone());
+    match existing_metadata.loader {
+        ModLoader::Fabric => {
+            if command.fabric_loader_version.is_none() {
+                command.fabric_loader_version = existing_metadata.loader_version.clone();
+            }
+            if command.fabric_installer_version.is_none() {
+                command.fabric_installer_version = existing_metadata.installer_version.clone();
+            }
+        }
+        ModLoader::Paper => {
+            if command.paper_build.is_none() {
+                command.paper_build = existing_metadata
+                    .loader_version
+                    .as_deref()
+                    .and_then(|build| build.parse().ok());
+            }
+        }
+        ModLoader::Vanilla | ModLoader::Spigot | ModLoader::Bedrock => {}
+    }
+    if let Some(launch) = &existing_metadata.launch {
+        if command.custom_java_exe.is_none() && command.java_version.is_none() && !command.java_auto
+        {
+            command.custom_java_exe = Some(launch.java_path.clone());
+        }
+        if command.server_jar_name.is_none() {
+            command.server_jar_name = launch.server_jar_name.clone();
+        }
+        command.respect_java_opts |= launch.respect_java_opts;
+    }
+}
 
-    let version = command
-        .version
-        .as_deref()
-        .unwrap_or(&manifest.latest.release);
+// the loaders skip files that already exist when repairing, so only the run script and metadata are left
+fn finish_repair(
+    command: &NewCommand,
+    instance_path: &Path,
+    mut instance_metadata: InstanceMetadata,
+    launch: Option<LaunchMetadata>,
+    loader_version: Option<String>,
+    installer_version: Option<String>,
+    eula_accepted: bool,
+) -> anyhow::Result<()> {
+    if let Some(mut launch) = launch {
+        if let Some(existing_launch) = &instance_metadata.launch {
+            if command.max_memory.is_none() && !command.memory_auto {
+                launch.memory = existing_launch.memory;
+            }
+            // --jvm-arg can't be passed with --repair, so the new launch only has the loader's args.
+            // The recorded ones that the loader didn't add came from the user
+            let user_jvm_args: Vec<_> = existing_launch
+                .jvm_args
+                .iter()
+                .filter(|arg| !launch.jvm_args.contains(arg))
+                .cloned()
+                .collect();
+            launch.jvm_args.extend(user_jvm_args);
+        }
+        let run_server_path = instance_path.join(RUN_SERVER_FILENAME);
+        if !command.jar_only && !run_server_path.exists() {
+            status!("recreating {}", run_server_path.display());
+            write_run_server_file(instance_path, command.loader, &launch)?;
+        }
+        instance_metadata.launch = Some(launch);
+    }
+    if loader_version.is_some() {
+        instance_metadata.loader_version = loader_version;
+    }
+    if installer_version.is_some() {
+        instance_metadata.installer_version = installer_version;
+    }
+    instance_metadata.eula_accepted |= eula_accepted;
+    instance_metadata.save(instance_path, command.metadata.as_deref())?;
+
+    status!("repaired {}", instance_path.display());
+    Ok(())
+}
+
+fn create_default_config_template(config_template: &Path) -> anyhow::Result<()> {
+    if config_template.exists() {
+        return Ok(());
+    }
+
+    // sync-chunk-writes is on by default but super slow on unix systems
+    #[cfg(unix)]
+    let default_server_properties = concat!(
+        "sync-chunk-writes=false\n",
+        include_str!("../../res/default-server.properties")
+    );
+    #[cfg(not(unix))]
+    let default_server_properties = include_str!("../../res/default-server.properties");
+
+    fs::create_dir(config_template).with_path_context(config_template)?;
+    let properties_template_path = config_template.join("server.properties");
+    fs::write(&properties_template_path, default_server_properties)
+        .with_path_context(&properties_template_path)?;
+    Ok(())
+}
+
+// the width and height are the first thing in a png, in the IHDR chunk straight after the signature
+fn check_server_icon(icon: &Path) -> anyhow::Result<()> {
+    let mut header = [0; 24];
+    let mut file = File::open(icon).with_path_context(icon)?;
+    let is_png = file.read_exact(&mut header).is_ok()
+        && header.starts_with(PNG_SIGNATURE)
+        && &header[12..16] == b"IHDR";
+    if !is_png {
+        bail!("server icon {} is not a PNG image", icon.display());
+    }
+    let width = u32::from_be_bytes(header[16..20].try_into().unwrap());
+    let height = u32::from_be_bytes(header[20..24].try_into().unwrap());
+    if width != SERVER_ICON_SIZE || height != SERVER_ICON_SIZE {
+        eprintln!(
+            "warning: server icon {} is {width}x{height}, Minecraft only shows icons that are {SERVER_ICON_SIZE}x{SERVER_ICON_SIZE}",
+            icon.display()
+        );
+    }
+    Ok(())
+}
+
+fn print_summary(
+    command: &NewCommand,
+    instance_path: &Path,
+    version: &str,
+    loader_version: Option<&str>,
+    java_candidate: Option<&JavaCandidate>,
+) {
+    status!(
+        "created {} server {} in {}",
+        command.loader,
+        command.name(),
+        instance_path.display()
+    );
+    status!("  minecraft version: {version}");
+    match loader_version {
+        Some(loader_version) if command.loader == ModLoader::Paper => {
+            status!("  loader: paper build {loader_version}")
+        }
+        Some(loader_version) => status!("  loader: {} {loader_version}", command.loader),
+        None => status!("  loader: {}", command.loader),
+    }
+    if let Some(java_candidate) = java_candidate {
+        status!("  java: {java_candidate}");
+    }
+    if let Some(level_name) = &command.level_name {
+        status!("  level name: {level_name}");
+    }
+    if let Some(seed) = &command.seed {
+        status!("  seed: {seed}");
+    }
+    if let Some(world_storage) = &command.world_storage {
+        status!("  world storage: {}", world_storage.display());
+    }
+    if command.loader != ModLoader::Bedrock {
+        if instance_path.join(SERVER_ICON_FILENAME).exists() {
+            status!("  icon: present");
+        } else {
+            status!("  icon: absent");
+        }
+    }
+
+    // bedrock refuses to install without agreeing, and --jar-only leaves the EULA up to the user
+    let eula_path = instance_path.join("eula.txt");
+    let eula_accepted = command.loader == ModLoader::Bedrock
+        || command.jar_only
+        || fs::read_to_string(&eula_path)
+            .is_ok_and(|eula| eula.lines().any(|line| line.trim() == "eula=true"));
+    if !eula_accepted {
+        eprintln!(
+            "note: the Minecraft EULA has not been accepted, the server won't start until eula=true is set in {}",
+            eula_path.display()
+        );
+    }
+
+    if command.standalone {
+        status!("  the instance is standalone, it can be moved or archived without the cache");
+    }
+
+    if command.docker {
+        status!("to start the server in docker, run:");
+        status!("  cd {}", instance_path.display());
+        status!("  docker compose up -d");
+    }
+
+    if command.jar_only {
+        status!("no run script was written because of --jar-only");
+        return;
+    }
+    status!("to start the server, run:");
+    status!("  cd {}", instance_path.display());
+    #[cfg(target_os = "windows")]
+    status!("  {RUN_SERVER_FILENAME}");
+    #[cfg(not(target_os = "windows"))]
+    status!("  ./{RUN_SERVER_FILENAME}");
+}
+
+fn write_docker_files(
+    instance_path: &Path,
+    command: &NewCommand,
+    launch: &LaunchMetadata,
+    java_major: u32,
+) -> anyhow::Result<()> {
+    let image_java = TEMURIN_JRE_VERSIONS
+        .iter()
+        .copied()
+        .find(|&version| version >= java_major)
+        .unwrap_or(java_major);
+    if image_java != java_major {
+        eprintln!("warning: there is no eclipse-temurin image for java {java_major}, using java {image_java} in the container instead");
+    }
+    // the template or --property may have set these as well as --port and --level-name
+    let properties_path = instance_path.join("server.properties");
+    let port = match get_property(&properties_path, "server-port")? {
+        Some(port) => port
+            .parse()
+            .with_context(|| format!("invalid server-port {port} in server.properties"))?,
+        None => DEFAULT_SERVER_PORT,
+    };
+    let world_folders = world_folders(command.loader, &get_level_name(instance_path)?);
+
+    // the java path in the metadata is the host's, the container has its own java on the path
+    let mut container_command = vec!["java".to_owned()];
+    if launch.respect_java_opts {
+        // exec form doesn't expand variables, so let sh split $JAVA_OPTS and pass the rest through untouched
+        container_command = vec![
+            "sh".to_owned(),
+            "-c".to_owned(),
+            "exec java $JAVA_OPTS \"$@\"".to_owned(),
+            "java".to_owned(),
+        ];
+    }
+    container_command.extend(launch.all_jvm_args());
+    container_command.extend(
+        command
+            .loader
+            .launch_args(launch.server_jar_name.as_deref()),
+    );
+
+    let dockerfile = [
+        format!("FROM eclipse-temurin:{image_java}-jre"),
+        "WORKDIR /server".to_owned(),
+        "COPY . /server".to_owned(),
+        format!("EXPOSE {port}"),
+        format!("CMD {}", serde_json::to_string(&container_command)?),
+    ];
+    let dockerfile_path = instance_path.join("Dockerfile");
+    fs::write(&dockerfile_path, dockerfile.join("\n") + "\n")
+        .with_path_context(&dockerfile_path)?;
+
+    let mut compose = vec![
+        "services:".to_owned(),
+        "  server:".to_owned(),
+        "    build: .".to_owned(),
+        "    ports:".to_owned(),
+        format!("      - \"{port}:{port}\""),
+        "    volumes:".to_owned(),
+    ];
+    for folder in &world_folders {
+        compose.push(format!("      - ./{folder}:/server/{folder}"));
+    }
+    if launch.respect_java_opts {
+        // passed through from wherever docker compose is run
+        compose.push("    environment:".to_owned());
+        compose.push("      - JAVA_OPTS".to_owned());
+    }
+    // lets `docker attach` reach the server console
+    compose.push("    stdin_open: true".to_owned());
+    compose.push("    tty: true".to_owned());
+    let compose_path = instance_path.join("docker-compose.yml");
+    fs::write(&compose_path, compose.join("\n") + "\n").with_path_context(&compose_path)?;
+
+    // the world is mounted as a volume, so keep it out of the image
+    let mut dockerignore = world_folders;
+    dockerignore.push("Dockerfile".to_owned());
+    dockerignore.push("docker-compose.yml".to_owned());
+    let dockerignore_path = instance_path.join(".dockerignore");
+    fs::write(&dockerignore_path, dockerignore.join("\n") + "\n")
+        .with_path_context(&dockerignore_path)?;
+
+    Ok(())
+}
+
+// for scripts that didn't pass --version, or passed an alias such as latest
+fn report_version(command: &NewCommand, version: &str) -> anyhow::Result<()> {
+    if let Some(version_out) = &command.version_out {
+        fs::write(version_out, format!("{version}{LINE_ENDING}")).with_path_context(version_out)?;
+    }
+    if command.print_version {
+        println!("{version}");
+    }
+    Ok(())
+}
+
+fn install_java_server(
+    command: &NewCommand,
+    client: &Client,
+    cache_dir: &Path,
+    instance_path: &Path,
+) -> anyhow::Result<(String, InstalledServer, JavaCandidate)> {
+    status!("fetching minecraft versions");
+    let manifest = Manifest::download(client, &cache_dir.join("version_manifest.json"))?;
+
+    let version = match command.version.as_deref() {
+        Some(version) => manifest.resolve_version_alias(version)?,
+        None => &manifest.latest.release,
+    };
+    if command.version.as_deref() != Some(version) {
+        verbose!("using minecraft version {version}");
+    }
+    let version = version.to_owned();
     let Some(manifest_version) = manifest.versions.into_iter().find(|ver| ver.id == version) else {
         bail!("no such version: {version}");
     };
 
-    eprintln!("fetching metadata for version {version}");
-    let version_metadata_path = cache_dir.join("version_metadata");
-    fs::create_dir_all(&version_metadata_path)?;
-    let full_version = manifest_version.download(
-        &client,
-        &version_metadata_path.join(format!("{version}.json")),
-    )?;
+    let full_version = download_version_metadata(client, cache_dir, &manifest_version)?;
+
+    if let Some(component) = &full_version.java_version.component {
+        verbose!(
+            "version {version} uses java runtime component {component} (java {})",
+            full_version.java_version.major_version
+        );
+    }
 
     let required_java_version = command
         .loader
@@ -50,13 +873,34 @@ pub fn make_new_instance(command: NewCommand, cache_dir: PathBuf) -> anyhow::Res
         }
         java_candidate
     } else {
-        eprintln!("searching for java versions");
+        status!("searching for java versions");
         let mut java_candidates = find_java_candidates()?;
         if !command.skip_java_check {
             java_candidates.retain(|candidate| candidate.version.major >= required_java_version);
         }
+        // the usual error below covers there being no compatible java at all
+        if let Some(java_arch) = command.java_arch.filter(|_| !java_candidates.is_empty()) {
+            let available_arches: BTreeSet<_> = java_candidates
+                .iter()
+                .filter_map(|candidate| candidate.arch)
+                .collect();
+            java_candidates.retain(|candidate| candidate.arch == Some(java_arch));
+            if java_candidates.is_empty() {
+                if available_arches.is_empty() {
+                    bail!("no {java_arch} java install was found, and the architecture of the other installs is unknown");
+                }
+                bail!(
+                    "no {java_arch} java install was found, found {}",
+                    available_arches
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
 
-        // sort by major version ascending (to most closely match the required java version), and then by version descending, to prioritize the latest of each major version.
+        // sort by major version ascending (to most closely match the required java version), then HotSpot-based runtimes first, and then by version descending, to prioritize the latest of each major version.
         // also put the versions that are too old at the end
         java_candidates.sort_by(|candidate1, candidate2| {
             let candidate1_old = candidate1.version.major < required_java_version;
@@ -71,11 +915,49 @@ pub fn make_new_instance(command: NewCommand, cache_dir: PathBuf) -> anyhow::Res
                 return cmp;
             }
 
+            let cmp = candidate2
+                .is_hotspot_based()
+                .cmp(&candidate1.is_hotspot_based());
+            if cmp != Ordering::Equal {
+                return cmp;
+            }
+
             candidate2.version.cmp(&candidate1.version)
         });
-        let Some(java_candidate) =
-            cli::select_from_list(java_candidates, "select java executable")?
-        else {
+        let java_candidate = if let Some(java_version) = command.java_version {
+            match java_candidates
+                .iter()
+                .position(|candidate| candidate.version.major == java_version)
+            {
+                Some(index) => {
+                    let java_candidate = java_candidates.remove(index);
+                    status!("using java executable {java_candidate}");
+                    Some(java_candidate)
+                }
+                None if java_candidates.is_empty() => None,
+                None => {
+                    let available_versions: BTreeSet<_> = java_candidates
+                        .iter()
+                        .map(|candidate| candidate.version.major)
+                        .collect();
+                    bail!(
+                        "could not find a compatible java {java_version} install, available java versions: {}",
+                        available_versions
+                            .iter()
+                            .map(|version| version.to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
+            }
+        } else {
+            cli::select_from_list(
+                java_candidates,
+                "select java executable",
+                command.yes || command.java_auto,
+            )?
+        };
+        let Some(java_candidate) = java_candidate else {
             bail!("could not find any java install compatible with {} {}, need at least java {required_java_version}", command.loader, version);
         };
         java_candidate
@@ -84,46 +966,49 @@ pub fn make_new_instance(command: NewCommand, cache_dir: PathBuf) -> anyhow::Res
         eprintln!("warning: selected java version {} is newer than the recommended java version {required_java_version}, which may cause issues", java_candidate.version);
     }
 
-    command.loader.install(ServerInstallArgs {
-        command: &command,
-        client: &client,
-        cache_dir: &cache_dir,
-        instance_path: &instance_path,
-        version_name: version,
+    let memory = JvmMemory::from_options(command.memory_auto, command.max_memory)?;
+
+    let installed = command.loader.install(ServerInstallArgs {
+        command,
+        client,
+        cache_dir,
+        instance_path,
+        version_name: &version,
         manifest_version: &manifest_version,
         full_version: &full_version,
         java_candidate: &java_candidate,
+        memory,
+        server_jar_name: command
+            .server_jar_name
+            .as_deref()
+            .filter(|_| command.loader.supports_server_jar_name()),
     })?;
 
-    if command.config_template == cache_dir.join("default-config-template")
-        && !command.config_template.exists()
-    {
-        // sync-chunk-writes is on by default but super slow on unix systems
-        #[cfg(unix)]
-        let default_server_properties = concat!(
-            "sync-chunk-writes=false\n",
-            include_str!("../../res/default-server.properties")
-        );
-        #[cfg(not(unix))]
-        let default_server_properties = include_str!("../../res/default-server.properties");
-
-        fs::create_dir(&command.config_template).with_path_context(&command.config_template)?;
-        let properties_template_path = command.config_template.join("server.properties");
-        fs::write(&properties_template_path, default_server_properties)
-            .with_path_context(&properties_template_path)?;
-    }
-
-    ioutil::copy_directory(&command.config_template, &instance_path).with_context(|| {
-        format!(
-            "copying from {} to {}",
-            command.config_template.display(),
-            instance_path.display()
-        )
-    })?;
+    Ok((version, installed, java_candidate))
+}
 
-    InstanceMetadata::new(command.loader, version).save(&instance_path)?;
+pub fn download_version_metadata(
+    client: &Client,
+    cache_dir: &Path,
+    manifest_version: &ManifestVersion,
+) -> anyhow::Result<Version> {
+    let version = &manifest_version.id;
+    status!("fetching metadata for version {version}");
+    let version_metadata_path = cache_dir.join("version_metadata");
+    fs::create_dir_all(&version_metadata_path)?;
+    manifest_version.download(
+        client,
+        &version_metadata_path.join(format!("{version}.json")),
+    )
+}
 
-    Ok(())
+pub struct InstalledServer {
+    pub launch: LaunchMetadata,
+    // the loader version or build that was installed, if the loader has one
+    pub loader_version: Option<String>,
+    // the fabric installer the launcher was generated with
+    pub installer_version: Option<String>,
+    pub eula_accepted: bool,
 }
 
 pub struct ServerInstallArgs<'a> {
@@ -135,21 +1020,62 @@ pub struct ServerInstallArgs<'a> {
     pub manifest_version: &'a ManifestVersion,
     pub full_version: &'a Version,
     pub java_candidate: &'a JavaCandidate,
+    pub memory: Option<JvmMemory>,
+    pub server_jar_name: Option<&'a str>,
 }
 
 impl ServerInstallArgs<'_> {
-    pub fn escaped_java_exe_name(&self) -> anyhow::Result<Cow<str>> {
-        Ok(escape_executable_name(
-            self.java_candidate
-                .path
-                .to_str()
-                .ok_or_else(|| anyhow!("java path had invalid UTF-8 characters"))?,
-        ))
+    pub fn launch_metadata(&self, jvm_args: Vec<String>) -> LaunchMetadata {
+        LaunchMetadata {
+            java_path: self.java_candidate.path.clone(),
+            memory: self.memory,
+            jvm_args: jvm_args
+                .into_iter()
+                .chain(self.command.jvm_args.iter().cloned())
+                .collect(),
+            server_jar_name: self.server_jar_name.map(ToOwned::to_owned),
+            respect_java_opts: self.command.respect_java_opts,
+        }
+    }
+
+    // --jar-only leaves launching the server up to the user
+    pub fn write_launch_files(&self, launch: &LaunchMetadata) -> anyhow::Result<bool> {
+        if self.command.jar_only {
+            return Ok(false);
+        }
+        // when repairing, the run script is written once the recorded settings are merged back in
+        if !self.command.repair {
+            write_run_server_file(self.instance_path, self.command.loader, launch)?;
+        }
+        agree_to_eula(self)
+    }
+
+    // puts a jar from the cache into the instance
+    pub fn link_jar(&self, target: &Path, link_path: &Path) -> anyhow::Result<()> {
+        if self.command.repair && link_path.exists() {
+            verbose!("keeping existing {}", link_path.display());
+            return Ok(());
+        }
+        if self.command.standalone {
+            // don't copy through a symlink left behind by a previous non-standalone install
+            if fs::symlink_metadata(link_path).is_ok_and(|metadata| metadata.is_symlink()) {
+                fs::remove_file(link_path).with_path_context(link_path)?;
+            }
+            fs::copy(target, link_path).with_context(|| {
+                format!("copying {} to {}", target.display(), link_path.display())
+            })?;
+        } else {
+            ioutil::link_or_copy(target, link_path, self.command.link_cache_relative)
+                .with_context(|| {
+                    format!("linking {} to {}", link_path.display(), target.display())
+                })?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(windows)]
-fn escape_executable_name(exe_name: &str) -> Cow<str> {
+fn escape_executable_name(exe_name: &str) -> Cow<'_, str> {
     fn char_needs_escape(c: char) -> bool {
         if c.is_whitespace() {
             return true;
@@ -168,7 +1094,7 @@ fn escape_executable_name(exe_name: &str) -> Cow<str> {
 }
 
 #[cfg(not(windows))]
-fn escape_executable_name(exe_name: &str) -> Cow<str> {
+fn escape_executable_name(exe_name: &str) -> Cow<'_, str> {
     fn char_needs_escape(index: usize, c: char) -> bool {
         if c.is_whitespace() {
             return true;
@@ -191,8 +1117,71 @@ fn escape_executable_name(exe_name: &str) -> Cow<str> {
     format!("'{}'", exe_name.replace('\'', "'\\''")).into()
 }
 
-pub fn write_run_server_file(args: &ServerInstallArgs<'_>, command: &str) -> anyhow::Result<()> {
-    let run_server_path = args.instance_path.join(RUN_SERVER_FILENAME);
+// jvm args come from the user, so they're quoted like the java path but may contain = anywhere
+#[cfg(windows)]
+fn escape_argument(arg: &str) -> Cow<'_, str> {
+    const SPECIAL_CHARS: &str = "%^&<>|\"(),;";
+    if !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || SPECIAL_CHARS.contains(c))
+    {
+        return arg.into();
+    }
+
+    format!("\"{}\"", arg.replace('"', "\"\"").replace('%', "%%")).into()
+}
+
+#[cfg(not(windows))]
+fn escape_argument(arg: &str) -> Cow<'_, str> {
+    const SPECIAL_CHARS: &str = "!\"#$&'()*;<>?[\\]^`{|}~";
+    if !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || SPECIAL_CHARS.contains(c))
+    {
+        return arg.into();
+    }
+
+    format!("'{}'", arg.replace('\'', "'\\''")).into()
+}
+
+pub fn write_run_server_file(
+    instance_path: &Path,
+    loader: ModLoader,
+    launch: &LaunchMetadata,
+) -> anyhow::Result<()> {
+    let command = format!(
+        "{JDK_JAVA_OPTIONS_NOTE}{LINE_ENDING}{}",
+        server_launch_command(loader, launch)?
+    );
+
+    write_run_script(instance_path, &command)
+}
+
+// the command the run script runs
+pub fn server_launch_command(loader: ModLoader, launch: &LaunchMetadata) -> anyhow::Result<String> {
+    let java_exe_name = escape_executable_name(
+        launch
+            .java_path
+            .to_str()
+            .ok_or_else(|| anyhow!("java path had invalid UTF-8 characters"))?,
+    );
+    let mut java_command = format!("{java_exe_name} ");
+    if launch.respect_java_opts {
+        java_command.push_str(JAVA_OPTS_VAR);
+        java_command.push(' ');
+    }
+    for arg in launch.all_jvm_args() {
+        java_command.push_str(&escape_argument(&arg));
+        java_command.push(' ');
+    }
+
+    Ok(loader.launch_command(&java_command, launch.server_jar_name.as_deref()))
+}
+
+pub fn write_run_script(instance_path: &Path, command: &str) -> anyhow::Result<()> {
+    let run_server_path = instance_path.join(RUN_SERVER_FILENAME);
     let mut open_options = File::options();
     open_options.create(true).truncate(true).write(true);
     #[cfg(unix)]
@@ -200,8 +1189,45 @@ pub fn write_run_server_file(args: &ServerInstallArgs<'_>, command: &str) -> any
     open_options
         .open(&run_server_path)
         .with_path_context(&run_server_path)?
-        .write_all(command.as_bytes())
+        .write_all(format!("{CD_TO_SCRIPT_DIR}{LINE_ENDING}{command}").as_bytes())
         .with_path_context(&run_server_path)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[cfg(unix)]
+    #[test]
+    fn jvm_arg_is_passed_as_one_word() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // stands in for java, printing each argument it gets on its own line
+        let dir = TempDir::new("run_server").unwrap();
+        let fake_java = dir.path().join("java");
+        fs::write(&fake_java, "#!/bin/sh\nprintf '%s\\n' \"$@\"\n").unwrap();
+        fs::set_permissions(&fake_java, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let jvm_arg = "-Dmotd=hello $USER's world; echo oops";
+        let launch = LaunchMetadata {
+            java_path: fake_java,
+            memory: None,
+            jvm_args: vec![jvm_arg.to_owned()],
+            server_jar_name: None,
+            respect_java_opts: false,
+        };
+        let command = server_launch_command(ModLoader::Vanilla, &launch).unwrap();
+        let output = Command::new("sh").arg("-c").arg(&command).output().unwrap();
+        assert!(output.status.success());
+        let args: Vec<_> = std::str::from_utf8(&output.stdout)
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect();
+        assert_eq!(args[0], jvm_arg);
+        assert!(!args.iter().any(|arg| arg == "oops"));
+    }
+}
@@ -0,0 +1,86 @@
+use crate::cli::PrefetchCommand;
+use crate::commands::new::download_version_metadata;
+use crate::make_client;
+use crate::mod_loader::fabric::download_fabric_launcher;
+use crate::mod_loader::paper::download_paperclip;
+use crate::mod_loader::vanilla::download_server_jar;
+use crate::mod_loader::ModLoader;
+use crate::mojang::{Manifest, ManifestVersion};
+use crate::output::status;
+use anyhow::bail;
+use reqwest::blocking::Client;
+use std::path::{Path, PathBuf};
+
+pub fn prefetch_versions(command: PrefetchCommand, cache_dir: PathBuf) -> anyhow::Result<()> {
+    let client = make_client()?;
+
+    status!("fetching minecraft versions");
+    let manifest = Manifest::download(&client, &cache_dir.join("version_manifest.json"))?;
+
+    // check every version up front rather than failing halfway through
+    let mut manifest_versions = Vec::new();
+    for version in &command.versions {
+        let version = manifest.resolve_version_alias(version)?;
+        let Some(manifest_version) = manifest.versions.iter().find(|ver| ver.id == version) else {
+            bail!("no such version: {version}");
+        };
+        manifest_versions.push(manifest_version);
+    }
+
+    let mut failed = Vec::new();
+    for manifest_version in manifest_versions {
+        let version = &manifest_version.id;
+        let result = prefetch_version(&command, &client, &cache_dir, manifest_version);
+        if let Err(err) = result {
+            eprintln!("error: failed to prefetch {version}: {err:#}");
+            failed.push(version.as_str());
+        }
+    }
+
+    let succeeded = command.versions.len() - failed.len();
+    status!(
+        "prefetched {succeeded} versions into {}",
+        cache_dir.display()
+    );
+    if !failed.is_empty() {
+        bail!("failed to prefetch {}", failed.join(", "));
+    }
+
+    Ok(())
+}
+
+fn prefetch_version(
+    command: &PrefetchCommand,
+    client: &Client,
+    cache_dir: &Path,
+    manifest_version: &ManifestVersion,
+) -> anyhow::Result<()> {
+    let version = &manifest_version.id;
+    let full_version = download_version_metadata(client, cache_dir, manifest_version)?;
+    download_server_jar(client, cache_dir, version, &full_version, None)?;
+
+    match command.loader {
+        Some(ModLoader::Fabric) => {
+            let (_, loader_version, _) = download_fabric_launcher(
+                client,
+                cache_dir,
+                version,
+                None,
+                None,
+                command.loader_channel,
+            )?;
+            status!("prefetched {version} with fabric loader {loader_version}");
+        }
+        Some(ModLoader::Paper) => {
+            let (_, paper_build) =
+                download_paperclip(client, cache_dir, version, None, command.loader_channel)?;
+            status!("prefetched {version} with paper build {paper_build}");
+        }
+        Some(ModLoader::Vanilla) | None => status!("prefetched {version}"),
+        Some(loader @ (ModLoader::Spigot | ModLoader::Bedrock)) => {
+            bail!("{loader} servers can't be prefetched")
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use crate::cli::RegenScriptCommand;
+use crate::commands::new::{server_launch_command, write_run_server_file};
+use crate::instance::InstanceMetadata;
+use crate::java::create_java_candidate_for_path;
+use crate::memory::JvmMemory;
+use crate::mod_loader::ModLoader;
+use crate::output::status;
+use crate::RUN_SERVER_FILENAME;
+use anyhow::bail;
+use clap::crate_name;
+
+pub fn regenerate_script(command: RegenScriptCommand) -> anyhow::Result<()> {
+    let instance_path = &command.instance;
+    let mut instance_metadata = InstanceMetadata::load(instance_path, command.metadata.as_deref())?;
+
+    if instance_metadata.loader == ModLoader::Bedrock {
+        bail!("bedrock servers have no java launch settings to regenerate");
+    }
+
+    let Some(launch) = &mut instance_metadata.launch else {
+        bail!(
+            "instance has no launch settings recorded, it was likely created by an older version of {}",
+            crate_name!()
+        );
+    };
+
+    if let Some(java_exe) = command.custom_java_exe.clone() {
+        let java_candidate = create_java_candidate_for_path(java_exe, &mut None)?;
+        status!("using java executable {java_candidate}");
+        launch.java_path = java_candidate.path;
+    }
+
+    if command.no_memory {
+        launch.memory = None;
+    } else if command.memory_auto || command.max_memory.is_some() {
+        launch.memory = JvmMemory::from_options(command.memory_auto, command.max_memory)?;
+    }
+
+    if command.respect_java_opts {
+        launch.respect_java_opts = true;
+    } else if command.no_java_opts {
+        launch.respect_java_opts = false;
+    }
+
+    if command.print_command {
+        print!(
+            "{}",
+            server_launch_command(instance_metadata.loader, launch)?
+        );
+        return Ok(());
+    }
+
+    write_run_server_file(instance_path, instance_metadata.loader, launch)?;
+    instance_metadata.save(instance_path, command.metadata.as_deref())?;
+
+    status!(
+        "regenerated {}",
+        instance_path.join(RUN_SERVER_FILENAME).display()
+    );
+
+    Ok(())
+}
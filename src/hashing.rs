@@ -1,12 +1,14 @@
-use serde::de::{Error, Expected, Unexpected};
+use serde::de::value::{self, StrDeserializer};
+use serde::de::{Error, Expected, IntoDeserializer, Unexpected};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use sha1::{Digest, Sha1};
 use sha2::{Sha256, Sha512};
 use std::any::Any;
 use std::fmt::{Display, Formatter};
 use std::io::Write;
+use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HexString<const N: usize> {
     pub inner: [u8; N],
 }
@@ -68,6 +70,21 @@ impl<'de, const N: usize> Deserialize<'de> for HexString<N> {
     }
 }
 
+impl<const N: usize> FromStr for HexString<N> {
+    type Err = value::Error;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let deserializer: StrDeserializer<'_, value::Error> = str.into_deserializer();
+        HexString::deserialize(deserializer)
+    }
+}
+
+impl<const N: usize> Display for HexString<N> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&to_hex_string(&self.inner))
+    }
+}
+
 impl<const N: usize> Serialize for HexString<N> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
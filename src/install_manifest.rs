@@ -0,0 +1,73 @@
+use crate::ioutil::{take_recorded_downloads, DownloadRecord};
+use crate::java::JavaCandidate;
+use crate::mod_loader::ModLoader;
+use crate::ContextExt;
+use clap::{crate_name, crate_version};
+use serde::Serialize;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use time::OffsetDateTime;
+
+// a record of exactly what went into an instance, unlike InstanceMetadata nothing reads this back
+#[derive(Debug, Serialize)]
+struct InstallManifest {
+    generator: String,
+    #[serde(with = "time::serde::rfc3339")]
+    started_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    finished_at: OffsetDateTime,
+    minecraft_version: String,
+    loader: ModLoader,
+    // the fabric loader version or paper build
+    #[serde(skip_serializing_if = "Option::is_none")]
+    loader_version: Option<String>,
+    // None for bedrock
+    #[serde(skip_serializing_if = "Option::is_none")]
+    java: Option<JavaManifest>,
+    downloads: Vec<DownloadRecord>,
+}
+
+#[derive(Debug, Serialize)]
+struct JavaManifest {
+    path: PathBuf,
+    version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vm: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arch: Option<String>,
+}
+
+impl JavaManifest {
+    fn new(java_candidate: &JavaCandidate) -> Self {
+        Self {
+            path: java_candidate.path.clone(),
+            version: java_candidate.version.to_string(),
+            vm: java_candidate.vm.map(|vm| vm.to_string()),
+            arch: java_candidate.arch.map(|arch| arch.to_string()),
+        }
+    }
+}
+
+// the downloads are whatever was recorded since ioutil::start_recording_downloads
+pub fn write_install_manifest(
+    path: &Path,
+    started_at: OffsetDateTime,
+    minecraft_version: &str,
+    loader: ModLoader,
+    loader_version: Option<&str>,
+    java_candidate: Option<&JavaCandidate>,
+) -> anyhow::Result<()> {
+    let manifest = InstallManifest {
+        generator: format!("{} {}", crate_name!(), crate_version!()),
+        started_at,
+        finished_at: OffsetDateTime::now_utc(),
+        minecraft_version: minecraft_version.to_owned(),
+        loader,
+        loader_version: loader_version.map(ToOwned::to_owned),
+        java: java_candidate.map(JavaManifest::new),
+        downloads: take_recorded_downloads(),
+    };
+    let file = File::create(path).with_path_context(path)?;
+    serde_json::to_writer_pretty(file, &manifest).with_path_context(path)?;
+    Ok(())
+}
@@ -1,48 +1,183 @@
 use crate::hashing::HashWithAlgorithm;
+use crate::memory::JvmMemory;
 use crate::mod_loader::ModLoader;
 use crate::mod_provider::ModProvider;
+use crate::properties::get_level_name;
 use crate::ContextExt;
 use clap::crate_name;
 use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{fs, io, thread};
 
-const INSTANCE_METADATA_FILE: &str = concat!(".", crate_name!(), "_metadata.json");
+pub const INSTANCE_METADATA_FILE: &str = concat!(".", crate_name!(), "_metadata.json");
+const SAVE_ATTEMPTS: u32 = 5;
+const SAVE_RETRY_DELAY: Duration = Duration::from_millis(200);
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct InstanceMetadata {
+    #[serde(default)]
+    pub name: String,
     pub loader: ModLoader,
+    // the fabric loader version or paper build
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loader_version: Option<String>,
+    // the fabric installer version, None for other loaders and instances made before it was recorded
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub installer_version: Option<String>,
     pub minecraft_version: String,
     #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub launch: Option<LaunchMetadata>,
+    #[serde(default)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub mods: Vec<ModMetadata>,
+    // whether the eula was accepted through us, eula.txt may have been edited since
+    #[serde(default)]
+    pub eula_accepted: bool,
+    // where the world folders are linked to, from --world-storage
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub world_storage: Option<PathBuf>,
 }
 
 impl InstanceMetadata {
-    pub fn new(loader: ModLoader, minecraft_version: impl Into<String>) -> Self {
+    pub fn new(
+        name: impl Into<String>,
+        loader: ModLoader,
+        loader_version: Option<String>,
+        minecraft_version: impl Into<String>,
+        launch: Option<LaunchMetadata>,
+    ) -> Self {
         Self {
+            name: name.into(),
             loader,
+            loader_version,
+            installer_version: None,
             minecraft_version: minecraft_version.into(),
+            launch,
             mods: Vec::new(),
+            eula_accepted: false,
+            world_storage: None,
         }
     }
 
-    pub fn load(instance_dir: &Path) -> anyhow::Result<InstanceMetadata> {
-        let metadata_file = instance_dir.join(INSTANCE_METADATA_FILE);
+    pub fn exists(instance_dir: &Path, metadata_path: Option<&Path>) -> bool {
+        Self::metadata_file(instance_dir, metadata_path).exists()
+    }
+
+    // metadata_path overrides the metadata file inside the instance, e.g. when it's kept in a separate repo
+    fn metadata_file(instance_dir: &Path, metadata_path: Option<&Path>) -> PathBuf {
+        match metadata_path {
+            Some(metadata_path) => metadata_path.to_owned(),
+            None => instance_dir.join(INSTANCE_METADATA_FILE),
+        }
+    }
+
+    pub fn load(
+        instance_dir: &Path,
+        metadata_path: Option<&Path>,
+    ) -> anyhow::Result<InstanceMetadata> {
+        let metadata_file = Self::metadata_file(instance_dir, metadata_path);
         let file = File::open(&metadata_file).with_path_context(&metadata_file)?;
         serde_json::from_reader(file).with_path_context(&metadata_file)
     }
 
-    pub fn save(&self, instance_dir: &Path) -> anyhow::Result<()> {
-        let metadata_file = instance_dir.join(INSTANCE_METADATA_FILE);
-        let file = File::options()
-            .create(true)
-            .truncate(true)
-            .write(true)
-            .open(&metadata_file)
-            .with_path_context(&metadata_file)?;
-        serde_json::to_writer_pretty(file, self).with_path_context(&metadata_file)?;
-        Ok(())
+    pub fn save(&self, instance_dir: &Path, metadata_path: Option<&Path>) -> anyhow::Result<()> {
+        let metadata_file = Self::metadata_file(instance_dir, metadata_path);
+        let contents = serde_json::to_vec_pretty(self).with_path_context(&metadata_file)?;
+        if cfg!(debug_assertions) {
+            if let Err(err) = serde_json::from_slice::<InstanceMetadata>(&contents) {
+                panic!("instance metadata doesn't load back after serializing: {err}");
+            }
+        }
+
+        // write next to the target and rename over it, so a crash never leaves half a file behind
+        let metadata_dir = match metadata_file.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let existing = fs::metadata(&metadata_file).ok();
+        let mut builder = tempfile::Builder::new();
+        if existing.is_none() {
+            use_default_permissions(&mut builder);
+        }
+        let mut temp_file = builder
+            .tempfile_in(metadata_dir)
+            .with_path_context(metadata_dir)?;
+        // temp files are only readable by us, keep whatever the file had before
+        if let Some(existing) = existing {
+            temp_file
+                .as_file()
+                .set_permissions(existing.permissions())
+                .with_path_context(temp_file.path())?;
+        }
+        temp_file
+            .write_all(&contents)
+            .and_then(|_| temp_file.as_file().sync_all())
+            .with_path_context(temp_file.path())?;
+
+        let mut attempt = 1;
+        loop {
+            match temp_file.persist(&metadata_file) {
+                Ok(_) => return Ok(()),
+                // on windows, a virus scanner or indexer may briefly hold the old file open
+                Err(err)
+                    if err.error.kind() == io::ErrorKind::PermissionDenied
+                        && attempt < SAVE_ATTEMPTS =>
+                {
+                    temp_file = err.file;
+                    attempt += 1;
+                    thread::sleep(SAVE_RETRY_DELAY);
+                }
+                Err(err) => return Err(err.error).with_path_context(&metadata_file),
+            }
+        }
+    }
+}
+
+// temp files are only readable by us, a new file should get the usual 0666 minus the umask instead
+#[cfg(unix)]
+fn use_default_permissions(builder: &mut tempfile::Builder<'_, '_>) {
+    use std::os::unix::fs::PermissionsExt;
+    // the mode is passed to open, which applies the umask
+    builder.permissions(fs::Permissions::from_mode(0o666));
+}
+
+#[cfg(not(unix))]
+fn use_default_permissions(_builder: &mut tempfile::Builder<'_, '_>) {}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LaunchMetadata {
+    pub java_path: PathBuf,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<JvmMemory>,
+    // jvm args required by the loader, such as the log4j fix, then those from --jvm-arg
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub jvm_args: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_jar_name: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub respect_java_opts: bool,
+}
+
+impl LaunchMetadata {
+    pub fn all_jvm_args(&self) -> Vec<String> {
+        let mut jvm_args = Vec::new();
+        if let Some(memory) = &self.memory {
+            memory.push_jvm_args(&mut jvm_args);
+        }
+        jvm_args.extend(self.jvm_args.iter().cloned());
+        jvm_args
     }
 }
 
@@ -52,5 +187,83 @@ pub struct ModMetadata {
     pub name: String,
     pub file_name: String,
     pub hash: HashWithAlgorithm,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
     pub provider: ModProvider,
+    #[serde(default)]
+    pub kind: ModKind,
+    // relative to the instance, only recorded for kinds whose folder can change, like datapacks
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+}
+
+impl ModMetadata {
+    pub fn folder(&self, loader: ModLoader) -> Option<String> {
+        self.folder
+            .clone()
+            .or_else(|| self.kind.folder(loader).map(ToOwned::to_owned))
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModKind {
+    #[default]
+    Mod,
+    ResourcePack,
+    Shader,
+    Datapack,
+}
+
+impl ModKind {
+    // None for datapacks too, they live inside the world, see install_folder
+    pub fn folder(&self, loader: ModLoader) -> Option<&'static str> {
+        match self {
+            Self::Mod => loader.mods_folder(),
+            Self::ResourcePack => match loader {
+                ModLoader::Vanilla | ModLoader::Fabric => Some("resourcepacks"),
+                ModLoader::Paper | ModLoader::Spigot | ModLoader::Bedrock => None,
+            },
+            Self::Shader => match loader {
+                ModLoader::Fabric => Some("shaderpacks"),
+                ModLoader::Vanilla | ModLoader::Paper | ModLoader::Spigot | ModLoader::Bedrock => {
+                    None
+                }
+            },
+            Self::Datapack => None,
+        }
+    }
+
+    // the folder to install into relative to the instance, which for datapacks depends on the world
+    pub fn install_folder(
+        &self,
+        instance_path: &Path,
+        loader: ModLoader,
+    ) -> anyhow::Result<Option<String>> {
+        match self {
+            Self::Datapack => match loader {
+                ModLoader::Bedrock => Ok(None),
+                ModLoader::Vanilla | ModLoader::Fabric | ModLoader::Paper | ModLoader::Spigot => {
+                    Ok(Some(format!(
+                        "{}/datapacks",
+                        get_level_name(instance_path)?
+                    )))
+                }
+            },
+            _ => Ok(self.folder(loader).map(ToOwned::to_owned)),
+        }
+    }
+}
+
+impl Display for ModKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Mod => "mod",
+            Self::ResourcePack => "resource pack",
+            Self::Shader => "shader",
+            Self::Datapack => "datapack",
+        })
+    }
 }
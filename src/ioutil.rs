@@ -1,21 +1,197 @@
-use crate::hashing::HashAlgorithm;
+use crate::hashing::{HashAlgorithm, HashWithAlgorithm, Sha2String};
+use crate::memory::parse_byte_size;
+use crate::mirror;
+use crate::output::verbose;
 use crate::ContextExt;
 use anyhow::{anyhow, bail, Context};
+use flate2::read::GzDecoder;
 use reqwest::blocking::Client;
 use reqwest::{IntoUrl, StatusCode};
 use serde::de::DeserializeOwned;
-use std::fmt::Display;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::io::{Cursor, Read, Write};
+use std::io::{Cursor, Read, Seek, Write};
 use std::marker::PhantomData;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use std::{fs, io};
+use tar::EntryType;
+use time::OffsetDateTime;
+use url::Url;
+use zip::ZipArchive;
 
-pub fn link_or_copy(target: impl AsRef<Path>, link_name: impl AsRef<Path>) -> io::Result<()> {
+const ZIP_MAGIC: &[u8; 4] = b"PK\x03\x04";
+// how many files are downloaded at once when installing many mods
+const PARALLEL_DOWNLOADS: usize = 4;
+// far deeper than any real config template, but shallow enough to error out instead of running away
+const MAX_TEMPLATE_DEPTH: usize = 64;
+
+static CACHE_OPTIONS: OnceLock<CacheOptions> = OnceLock::new();
+
+#[derive(Debug, Copy, Clone)]
+pub struct CacheOptions {
+    // ignore cached copies and always download again
+    pub refresh: bool,
+    // how long a cached copy is used for before it's revalidated with the server
+    pub ttl: Duration,
+}
+
+impl Default for CacheOptions {
+    fn default() -> Self {
+        Self {
+            refresh: false,
+            ttl: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+static DOWNLOAD_THROTTLE: OnceLock<Throttle> = OnceLock::new();
+
+// a token bucket shared by all downloads, so that parallel downloads share the limit
+struct Throttle {
+    bytes_per_sec: u64,
+    // the tokens available and when they were last topped up
+    state: Mutex<(f64, Instant)>,
+}
+
+// None unless something asked for a record of what was downloaded
+static DOWNLOAD_RECORD: Mutex<Option<Vec<DownloadRecord>>> = Mutex::new(None);
+
+#[derive(Debug, Serialize)]
+pub struct DownloadRecord {
+    pub url: String,
+    pub file: PathBuf,
+    // the hash the file was checked against, None if the source doesn't publish one
+    pub verified_hash: Option<HashWithAlgorithm>,
+    pub sha256: Sha2String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub fetched_at: OffsetDateTime,
+}
+
+pub fn start_recording_downloads() {
+    *DOWNLOAD_RECORD.lock().unwrap() = Some(Vec::new());
+}
+
+pub fn take_recorded_downloads() -> Vec<DownloadRecord> {
+    DOWNLOAD_RECORD.lock().unwrap().take().unwrap_or_default()
+}
+
+// files used from the cache are recorded too, they're still part of what was installed
+pub fn record_download(
+    url: &Url,
+    path: &Path,
+    verified_hash: Option<HashWithAlgorithm>,
+) -> anyhow::Result<()> {
+    if DOWNLOAD_RECORD.lock().unwrap().is_none() {
+        return Ok(());
+    }
+
+    let mut file = File::open(path).with_path_context(path)?;
+    let mut digest = Sha256::new();
+    io::copy(&mut file, &mut digest).with_path_context(path)?;
+    let record = DownloadRecord {
+        url: url.to_string(),
+        file: path.to_owned(),
+        verified_hash,
+        sha256: Sha2String {
+            inner: digest.finalize().into(),
+        },
+        fetched_at: OffsetDateTime::now_utc(),
+    };
+    if let Some(records) = &mut *DOWNLOAD_RECORD.lock().unwrap() {
+        records.push(record);
+    }
+    Ok(())
+}
+
+pub fn parse_download_speed(str: &str) -> anyhow::Result<u64> {
+    let bytes_per_sec = parse_byte_size(str, "download speed")?;
+    if bytes_per_sec == 0 {
+        bail!("download speed {str} is too small");
+    }
+    Ok(bytes_per_sec)
+}
+
+pub fn set_max_download_speed(bytes_per_sec: u64) {
+    let _ = DOWNLOAD_THROTTLE.set(Throttle {
+        bytes_per_sec,
+        state: Mutex::new((bytes_per_sec as f64, Instant::now())),
+    });
+}
+
+fn throttle_download(bytes: usize) {
+    let Some(throttle) = DOWNLOAD_THROTTLE.get() else {
+        return;
+    };
+    let rate = throttle.bytes_per_sec as f64;
+    let wait = {
+        let mut state = throttle.state.lock().unwrap();
+        let (tokens, last_refill) = &mut *state;
+        let now = Instant::now();
+        // allow bursts of up to a second's worth
+        *tokens = (*tokens + now.duration_since(*last_refill).as_secs_f64() * rate).min(rate);
+        *last_refill = now;
+        *tokens -= bytes as f64;
+        if *tokens < 0.0 {
+            Some(Duration::from_secs_f64(-*tokens / rate))
+        } else {
+            None
+        }
+    };
+    if let Some(wait) = wait {
+        thread::sleep(wait);
+    }
+}
+
+pub fn set_cache_options(options: CacheOptions) {
+    let _ = CACHE_OPTIONS.set(options);
+}
+
+fn cache_options() -> CacheOptions {
+    CACHE_OPTIONS.get().copied().unwrap_or_default()
+}
+
+fn with_file_name_suffix(file: &Path, suffix: &str) -> anyhow::Result<PathBuf> {
+    let mut file_name = file
+        .file_name()
+        .ok_or_else(|| anyhow!("failed to extract filename from {}", file.display()))?
+        .to_owned();
+    file_name.push(suffix);
+    Ok(file.with_file_name(file_name))
+}
+
+fn read_fetch_time(fetched_file: &Path) -> Option<SystemTime> {
+    let seconds = fs::read_to_string(fetched_file).ok()?.trim().parse().ok()?;
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_secs(seconds))
+}
+
+fn write_fetch_time(fetched_file: &Path) -> anyhow::Result<()> {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    fs::write(fetched_file, seconds.to_string()).with_path_context(fetched_file)
+}
+
+// relative links keep working when the cache and the instance are moved together, they're only made on unix
+pub fn link_or_copy(
+    target: impl AsRef<Path>,
+    link_name: impl AsRef<Path>,
+    relative: bool,
+) -> io::Result<()> {
     let target = fs::canonicalize(target)?;
     let target = &target;
     let link_name = link_name.as_ref();
 
+    // replace any existing file, which can happen when generating into a non-empty directory
+    if fs::symlink_metadata(link_name).is_ok_and(|metadata| !metadata.is_dir()) {
+        fs::remove_file(link_name)?;
+    }
+
     #[cfg(windows)]
     let result = match std::os::windows::fs::symlink_file(target, link_name) {
         Err(err) if err.raw_os_error() == Some(1) || err.raw_os_error() == Some(1314) => {
@@ -26,46 +202,399 @@ pub fn link_or_copy(target: impl AsRef<Path>, link_name: impl AsRef<Path>) -> io
         result => result,
     };
     #[cfg(unix)]
-    let result = match std::os::unix::fs::symlink(target, link_name) {
-        Err(err) if err.raw_os_error() == Some(1) => {
-            // EPERM returned when filesystem doesn't support symlinks,
-            // in contrast to EACCES for when the user is missing read or write perms (Rust translates both to PermissionDenied)
-            fs::copy(target, link_name).map(|_| ())
+    let relative_target = match link_name.parent() {
+        Some(link_dir) if relative => {
+            // a bare file name has an empty parent
+            let link_dir = fs::canonicalize(if link_dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                link_dir
+            })?;
+            Some(relative_path(&link_dir, target))
         }
-        result => result,
+        _ => None,
     };
+    #[cfg(unix)]
+    let result =
+        match std::os::unix::fs::symlink(relative_target.as_deref().unwrap_or(target), link_name) {
+            Err(err) if err.raw_os_error() == Some(1) => {
+                // EPERM returned when filesystem doesn't support symlinks,
+                // in contrast to EACCES for when the user is missing read or write perms (Rust translates both to PermissionDenied)
+                fs::copy(target, link_name).map(|_| ())
+            }
+            result => result,
+        };
     #[cfg(not(any(windows, unix)))]
     let result = fs::copy(target, link_name).map(|_| ());
+    #[cfg(not(unix))]
+    let _ = relative;
 
     result
 }
 
-pub fn copy_directory(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
-    let src = src.as_ref();
-    let dst = dst.as_ref();
+// links a directory to one elsewhere, creating the target if needed. An existing empty directory is replaced
+pub fn link_directory(target: &Path, link_name: &Path) -> io::Result<()> {
+    fs::create_dir_all(target)?;
+    let target = fs::canonicalize(target)?;
 
-    let entries = fs::read_dir(src)?;
+    if let Ok(metadata) = fs::symlink_metadata(link_name) {
+        if metadata.is_symlink() && fs::canonicalize(link_name).is_ok_and(|dest| dest == target) {
+            return Ok(());
+        }
+        let is_empty_dir = metadata.is_dir()
+            && fs::read_dir(link_name).is_ok_and(|mut entries| entries.next().is_none());
+        if !is_empty_dir {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already exists, move it to {} first",
+                    link_name.display(),
+                    target.display()
+                ),
+            ));
+        }
+        fs::remove_dir(link_name)?;
+    }
+
+    #[cfg(windows)]
+    let result = match std::os::windows::fs::symlink_dir(&target, link_name) {
+        Err(err) if err.raw_os_error() == Some(1) || err.raw_os_error() == Some(1314) => {
+            // junctions don't need the privilege symlinks do, but only work for local paths
+            let status = std::process::Command::new("cmd")
+                .arg("/C")
+                .arg("mklink")
+                .arg("/J")
+                .arg(link_name)
+                .arg(&target)
+                .stdout(std::process::Stdio::null())
+                .status()?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!(
+                    "could not create a symlink or junction from {} to {}, try enabling developer mode or running as administrator",
+                    link_name.display(),
+                    target.display()
+                )))
+            }
+        }
+        result => result,
+    };
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(&target, link_name);
+    #[cfg(not(any(windows, unix)))]
+    let result = Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "directory links are not supported on this platform",
+    ));
+
+    result
+}
+
+// both paths must be absolute and canonical
+#[cfg(unix)]
+fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from_components: Vec<_> = from_dir.components().collect();
+    let to_components: Vec<_> = to.components().collect();
+    let common = from_components
+        .iter()
+        .zip(&to_components)
+        .take_while(|(from, to)| from == to)
+        .count();
+
+    let mut path = PathBuf::new();
+    for _ in common..from_components.len() {
+        path.push("..");
+    }
+    for component in &to_components[common..] {
+        path.push(component);
+    }
+    path
+}
+
+// templates are user provided, so symlinks in them may point back up the tree.
+// Calls visit with each path, its path relative to root and whether it's a directory, depth first in name order
+fn walk_directory(
+    root: &Path,
+    mut visit: impl FnMut(&Path, &Path, bool) -> io::Result<()>,
+) -> io::Result<()> {
+    struct PendingEntry {
+        path: PathBuf,
+        relative: PathBuf,
+        // the canonical paths of the directories it's inside, None for files
+        ancestors: Option<Vec<PathBuf>>,
+    }
+
+    let mut pending = vec![PendingEntry {
+        path: root.to_owned(),
+        relative: PathBuf::new(),
+        ancestors: Some(Vec::new()),
+    }];
+
+    while let Some(PendingEntry {
+        path,
+        relative,
+        ancestors,
+    }) = pending.pop()
+    {
+        let Some(mut ancestors) = ancestors else {
+            visit(&path, &relative, false)?;
+            continue;
+        };
+
+        let canonical_path = fs::canonicalize(&path)?;
+        if let Some(ancestor) = ancestors
+            .iter()
+            .find(|ancestor| **ancestor == canonical_path)
+        {
+            return Err(io::Error::other(format!(
+                "{} links back to {}, which it is inside of",
+                path.display(),
+                ancestor.display()
+            )));
+        }
+        if ancestors.len() >= MAX_TEMPLATE_DEPTH {
+            return Err(io::Error::other(format!(
+                "{} is nested more than {MAX_TEMPLATE_DEPTH} directories deep",
+                path.display()
+            )));
+        }
+        ancestors.push(canonical_path);
+
+        if !relative.as_os_str().is_empty() {
+            visit(&path, &relative, true)?;
+        }
+
+        let mut entries = fs::read_dir(&path)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        // pushed in reverse so that they're popped in name order
+        for entry in entries.into_iter().rev() {
+            let entry_path = entry.path();
+            let is_dir = entry_path.is_dir();
+            pending.push(PendingEntry {
+                relative: relative.join(entry.file_name()),
+                path: entry_path,
+                ancestors: is_dir.then(|| ancestors.clone()),
+            });
+        }
+    }
 
+    Ok(())
+}
+
+pub fn copy_directory(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let dst = dst.as_ref();
     if !dst.exists() {
         fs::create_dir_all(dst)?;
     }
 
-    for entry in entries {
-        let entry = entry?;
+    let mut copied = Vec::new();
+    walk_directory(src.as_ref(), |path, relative, is_dir| {
+        let dst_path = dst.join(relative);
+        if is_dir {
+            if !dst_path.exists() {
+                fs::create_dir_all(&dst_path)?;
+            }
+        } else {
+            fs::copy(path, &dst_path)?;
+            copied.push(dst_path);
+        }
+        Ok(())
+    })?;
+
+    Ok(copied)
+}
 
-        let src_entry_path = entry.path();
-        let dst_entry_path = dst.join(entry.file_name());
+// config templates can be distributed as a single archive instead of a directory, returns the files written
+pub fn copy_template(src: &Path, dst: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let file_name = src
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if src.is_dir() {
+        copy_directory(src, dst)
+            .with_context(|| format!("copying from {} to {}", src.display(), dst.display()))
+    } else if file_name.ends_with(".zip") {
+        extract_zip(src, dst)
+            .with_context(|| format!("extracting {} to {}", src.display(), dst.display()))
+    } else if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+        extract_tar_gz(src, dst)
+            .with_context(|| format!("extracting {} to {}", src.display(), dst.display()))
+    } else {
+        bail!(
+            "config template {} must be a directory, a .zip file or a .tar.gz file",
+            src.display()
+        );
+    }
+}
 
-        if src_entry_path.is_dir() {
-            copy_directory(src_entry_path, dst_entry_path)?;
-        } else {
-            fs::copy(src_entry_path, dst_entry_path)?;
+// directories are hashed file by file in a fixed order, so the hash doesn't depend on the platform
+pub fn hash_template(src: &Path) -> anyhow::Result<Sha2String> {
+    let mut hasher = HashAlgorithm::Sha256.create_hasher();
+    if src.is_dir() {
+        hash_directory(src, &mut hasher)?;
+    } else {
+        let mut file = File::open(src).with_path_context(src)?;
+        io::copy(&mut file, &mut hasher).with_path_context(src)?;
+    }
+    let mut hash = Sha2String { inner: [0; 32] };
+    hash.inner.copy_from_slice(&hasher.finalize());
+    Ok(hash)
+}
+
+fn hash_directory(dir: &Path, hasher: &mut impl Write) -> anyhow::Result<()> {
+    walk_directory(dir, |path, relative, is_dir| {
+        if is_dir {
+            return Ok(());
+        }
+        let name = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let contents = fs::read(path)?;
+        // the name and length keep different layouts of the same bytes from colliding
+        hasher.write_all(name.as_bytes())?;
+        hasher.write_all(&[0])?;
+        hasher.write_all(&(contents.len() as u64).to_le_bytes())?;
+        hasher.write_all(&contents)
+    })
+    .with_path_context(dir)
+}
+
+// rejects absolute paths and paths containing `..` so that an archive can't write outside dst
+pub fn archive_entry_path(dst: &Path, name: &Path) -> anyhow::Result<PathBuf> {
+    let mut path = dst.to_owned();
+    for component in name.components() {
+        match component {
+            Component::Normal(component) => path.push(component),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                bail!(
+                    "archive entry {} points outside of the destination directory",
+                    name.display()
+                );
+            }
+        }
+    }
+    Ok(path)
+}
+
+pub fn extract_zip(src: &Path, dst: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let file = File::open(src).with_path_context(src)?;
+    let mut archive = ZipArchive::new(file).with_path_context(src)?;
+
+    let mut extracted = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).with_path_context(src)?;
+        let entry_path = archive_entry_path(dst, Path::new(entry.name()))?;
+        if entry.is_dir() {
+            fs::create_dir_all(&entry_path).with_path_context(&entry_path)?;
+            continue;
+        }
+        if let Some(parent) = entry_path.parent() {
+            fs::create_dir_all(parent).with_path_context(parent)?;
+        }
+        let mut out = File::create(&entry_path).with_path_context(&entry_path)?;
+        io::copy(&mut entry, &mut out).with_path_context(&entry_path)?;
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&entry_path, fs::Permissions::from_mode(mode & 0o777))
+                .with_path_context(&entry_path)?;
+        }
+        extracted.push(entry_path);
+    }
+
+    Ok(extracted)
+}
+
+fn extract_tar_gz(src: &Path, dst: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let file = File::open(src).with_path_context(src)?;
+    let mut archive = tar::Archive::new(GzDecoder::new(file));
+
+    let mut extracted = Vec::new();
+    for entry in archive.entries().with_path_context(src)? {
+        let mut entry = entry.with_path_context(src)?;
+        let name = entry.path().with_path_context(src)?.into_owned();
+        let entry_path = archive_entry_path(dst, &name)?;
+        match entry.header().entry_type() {
+            EntryType::Directory => {
+                fs::create_dir_all(&entry_path).with_path_context(&entry_path)?;
+            }
+            EntryType::Regular | EntryType::Continuous => {
+                if let Some(parent) = entry_path.parent() {
+                    fs::create_dir_all(parent).with_path_context(parent)?;
+                }
+                let mut out = File::create(&entry_path).with_path_context(&entry_path)?;
+                io::copy(&mut entry, &mut out).with_path_context(&entry_path)?;
+                extracted.push(entry_path);
+            }
+            // links could point outside of the destination directory, and nothing else makes sense in a template
+            entry_type => bail!(
+                "archive entry {} has unsupported type {:?}",
+                name.display(),
+                entry_type
+            ),
         }
     }
 
+    Ok(extracted)
+}
+
+pub fn validate_jar(path: &Path) -> anyhow::Result<()> {
+    let file = File::open(path).with_path_context(path)?;
+    check_jar(file).with_path_context(path)
+}
+
+fn check_jar(mut reader: impl Read + Seek) -> anyhow::Result<()> {
+    check_jar_magic(&mut reader)?;
+    reader.rewind()?;
+    // the central directory is at the end of the file, so this catches truncated downloads
+    if ZipArchive::new(reader).is_err() {
+        bail!("downloaded file is not a valid jar (the download may have been truncated)");
+    }
+    Ok(())
+}
+
+fn check_jar_magic(mut reader: impl Read) -> anyhow::Result<()> {
+    let mut magic = [0; 4];
+    let valid = match reader.read_exact(&mut magic) {
+        Ok(()) => &magic == ZIP_MAGIC,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => false,
+        Err(err) => return Err(err.into()),
+    };
+    if !valid {
+        bail!("downloaded file is not a valid jar (got an HTML/error page?)");
+    }
     Ok(())
 }
 
+// runs f on every item on a few threads at once, returning the results in the same order as the items
+pub fn parallel_map<T, R>(items: Vec<T>, f: impl Fn(T) -> R + Sync) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+{
+    let len = items.len();
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let results = Mutex::new(Vec::with_capacity(len));
+    thread::scope(|scope| {
+        for _ in 0..PARALLEL_DOWNLOADS.min(len) {
+            scope.spawn(|| loop {
+                let Some((index, item)) = queue.lock().unwrap().next() else {
+                    break;
+                };
+                let result = f(item);
+                results.lock().unwrap().push((index, result));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
 pub fn is_not_found(err: &io::Error) -> bool {
     if err.kind() == io::ErrorKind::NotFound {
         return true;
@@ -91,27 +620,104 @@ pub fn is_not_found(err: &io::Error) -> bool {
     raw_os_error == not_a_directory_error
 }
 
+pub fn is_storage_full(err: &io::Error) -> bool {
+    // quota errors are reported separately but mean the same thing to the user
+    matches!(
+        err.kind(),
+        io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded
+    )
+}
+
 pub fn download_with_etag<T>(
     client: &Client,
     url: impl IntoUrl + Copy + Display,
     file: &Path,
     deserializer: impl GenericDeserializer<T>,
 ) -> anyhow::Result<T> {
-    let mut file_name = file
-        .file_name()
-        .ok_or_else(|| anyhow!("failed to extract filename from {}", file.display()))?
-        .to_owned();
-    file_name.push(".etag");
-    let etag_file = file.with_file_name(file_name);
-    let etag = match fs::read(&etag_file) {
-        Ok(etag) => Some(etag),
-        Err(err) if is_not_found(&err) => None,
-        Err(err) => return Err(err).with_path_context(&etag_file),
+    let result = fetch_with_etag(client, url, file, deserializer, false)?;
+    record_download(&url.into_url()?, file, None)?;
+    Ok(result)
+}
+
+// like download_with_etag, but always asks the server whether the cached copy is still current
+pub fn revalidate_with_etag<T>(
+    client: &Client,
+    url: impl IntoUrl + Copy + Display,
+    file: &Path,
+    deserializer: impl GenericDeserializer<T>,
+) -> anyhow::Result<T> {
+    let result = fetch_with_etag(client, url, file, deserializer, true)?;
+    record_download(&url.into_url()?, file, None)?;
+    Ok(result)
+}
+
+// lets callers tell an error status apart from other failures, e.g. a 404 for something that doesn't exist yet
+#[derive(Debug)]
+pub struct StatusCodeError {
+    pub url: String,
+    pub status: StatusCode,
+}
+
+impl Display for StatusCodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "request to {} returned status code {}",
+            self.url, self.status
+        )
+    }
+}
+
+impl std::error::Error for StatusCodeError {}
+
+pub fn has_status(err: &anyhow::Error, status: StatusCode) -> bool {
+    err.downcast_ref::<StatusCodeError>()
+        .is_some_and(|err| err.status == status)
+}
+
+fn fetch_with_etag<T>(
+    client: &Client,
+    url: impl IntoUrl + Copy + Display,
+    file: &Path,
+    deserializer: impl GenericDeserializer<T>,
+    revalidate: bool,
+) -> anyhow::Result<T> {
+    let cache_options = cache_options();
+    let etag_file = with_file_name_suffix(file, ".etag")?;
+    let last_modified_file = with_file_name_suffix(file, ".last-modified")?;
+    let fetched_file = with_file_name_suffix(file, ".fetched")?;
+
+    if !cache_options.refresh && !revalidate {
+        let fresh = read_fetch_time(&fetched_file)
+            .and_then(|fetched| fetched.elapsed().ok())
+            .is_some_and(|age| age < cache_options.ttl);
+        if fresh {
+            if let Ok(cached_file) = File::open(file) {
+                if let Ok(result) = deserializer.deserialize_reader(cached_file) {
+                    verbose!("using cached {}", file.display());
+                    return Ok(result);
+                }
+            }
+        }
+    }
+
+    // some proxies strip etags, fall back to the modification time when there isn't one
+    let (etag, last_modified) = if cache_options.refresh {
+        (None, None)
+    } else {
+        (
+            read_validator(&etag_file)?,
+            read_validator(&last_modified_file)?,
+        )
     };
 
-    let mut request = client.get(url);
+    verbose!("fetching {url}");
+    let request_url = url.into_url()?;
+    let mut request = mirror::authorize(client.get(request_url.clone()), &request_url);
     if let Some(etag) = etag {
         request = request.header("If-None-Match", etag);
+    } else if let Some(last_modified) = last_modified {
+        request = request.header("If-Modified-Since", last_modified);
     }
 
     let response = request.send().with_context(|| url.to_string())?;
@@ -119,26 +725,30 @@ pub fn download_with_etag<T>(
     if response.status() == StatusCode::NOT_MODIFIED {
         match File::open(file) {
             Ok(cached_file) => {
-                return deserializer
+                let result = deserializer
                     .deserialize_reader(cached_file)
-                    .with_path_context(file);
+                    .with_path_context(file)?;
+                write_fetch_time(&fetched_file)?;
+                return Ok(result);
             }
             Err(err) if is_not_found(&err) => {}
             Err(err) => return Err(err).with_path_context(file),
         }
     } else if !response.status().is_success() {
-        bail!(
-            "request to {} returned status code {}",
-            url,
-            response.status()
-        );
+        return Err(StatusCodeError {
+            url: url.to_string(),
+            status: response.status(),
+        }
+        .into());
     }
 
     let etag = response.headers().get("ETag").cloned();
+    let last_modified = response.headers().get("Last-Modified").cloned();
 
     let raw_json = response.bytes().with_context(|| url.to_string())?.to_vec();
 
     fs::write(&etag_file, "").with_path_context(&etag_file)?;
+    fs::write(&last_modified_file, "").with_path_context(&last_modified_file)?;
     fs::write(file, &raw_json).with_path_context(file)?;
     let result = deserializer
         .deserialize_slice(&raw_json)
@@ -146,11 +756,24 @@ pub fn download_with_etag<T>(
 
     if let Some(etag) = etag {
         fs::write(&etag_file, etag).with_path_context(&etag_file)?;
+    } else if let Some(last_modified) = last_modified {
+        fs::write(&last_modified_file, last_modified).with_path_context(&last_modified_file)?;
     }
+    write_fetch_time(&fetched_file)?;
 
     Ok(result)
 }
 
+// an empty file means the last response didn't have this header
+fn read_validator(file: &Path) -> anyhow::Result<Option<Vec<u8>>> {
+    match fs::read(file) {
+        Ok(value) if value.is_empty() => Ok(None),
+        Ok(value) => Ok(Some(value)),
+        Err(err) if is_not_found(&err) => Ok(None),
+        Err(err) => Err(err).with_path_context(file),
+    }
+}
+
 pub fn download_large_with_hash<U>(
     client: &Client,
     url: U,
@@ -163,16 +786,22 @@ pub fn download_large_with_hash<U>(
 where
     U: IntoUrl,
 {
+    let url = url.into_url()?;
+    let verified_hash = || HashWithAlgorithm {
+        algorithm,
+        hash: expected_hash.into(),
+    };
+
     if let Ok(mut existing_file) = File::open(path) {
         let mut digest = algorithm.create_hasher();
         if io::copy(&mut existing_file, &mut digest).is_ok() && &*digest.finalize() == expected_hash
         {
-            return Ok(());
+            verbose!("using cached {}", path.display());
+            return record_download(&url, path, Some(verified_hash()));
         }
     }
 
-    let url = url.into_url()?;
-    download_large(client, url.clone(), path, start_download, progress_listener)?;
+    fetch_large(client, &url, path, start_download, progress_listener)?;
 
     let mut file = File::open(path).with_path_context(path)?;
     let mut digest = algorithm.create_hasher();
@@ -184,7 +813,7 @@ where
         );
     }
 
-    Ok(())
+    record_download(&url, path, Some(verified_hash()))
 }
 
 pub fn download_large<U>(
@@ -192,12 +821,40 @@ pub fn download_large<U>(
     url: U,
     path: &Path,
     start_download: impl FnOnce(Option<u64>),
-    mut progress_listener: impl FnMut(u64),
+    progress_listener: impl FnMut(u64),
 ) -> anyhow::Result<()>
 where
     U: IntoUrl,
 {
     let url = url.into_url()?;
+    fetch_large(client, &url, path, start_download, progress_listener)?;
+    record_download(&url, path, None)
+}
+
+fn fetch_large(
+    client: &Client,
+    url: &Url,
+    path: &Path,
+    start_download: impl FnOnce(Option<u64>),
+    mut progress_listener: impl FnMut(u64),
+) -> anyhow::Result<()> {
+    verbose!("downloading {url} to {}", path.display());
+    let mut response = mirror::authorize(client.get(url.clone()), url)
+        .send()
+        .with_context(|| url.clone())?;
+    if matches!(response.status(), StatusCode::FORBIDDEN | StatusCode::GONE) {
+        bail!(
+            "{} is no longer available for download (status code {}), it may have been removed by its author",
+            url,
+            response.status()
+        );
+    } else if !response.status().is_success() {
+        bail!(
+            "request to {} returned status code {}",
+            url,
+            response.status()
+        );
+    }
 
     let mut file = File::options()
         .create(true)
@@ -205,28 +862,49 @@ where
         .write(true)
         .open(path)
         .with_path_context(path)?;
-    let mut response = client
-        .get(url.clone())
-        .send()
-        .with_context(|| url.clone())?;
     start_download(response.content_length());
     let mut downloaded = 0;
     let mut buffer = [0; 8192];
+    let mut write_result = Ok(());
     loop {
         let n = response.read(&mut buffer).with_context(|| url.clone())?;
         if n == 0 {
             break;
         }
-        file.write_all(&buffer[..n]).with_path_context(path)?;
+        if let Err(err) = file.write_all(&buffer[..n]) {
+            write_result = Err(err);
+            break;
+        }
         downloaded += n as u64;
         progress_listener(downloaded);
+        throttle_download(n);
     }
 
-    file.flush().with_path_context(path)?;
+    if let Err(err) = write_result.and_then(|()| file.flush()) {
+        // windows can't delete a file that is still open
+        drop(file);
+        return partial_write_error(err, path);
+    }
 
     Ok(())
 }
 
+// removes the partially written file so a truncated jar isn't picked up later
+fn partial_write_error(err: io::Error, path: &Path) -> anyhow::Result<()> {
+    if let Err(remove_err) = fs::remove_file(path) {
+        if !is_not_found(&remove_err) {
+            eprintln!(
+                "warning: could not remove the partially written {}: {remove_err}",
+                path.display()
+            );
+        }
+    }
+    if is_storage_full(&err) {
+        bail!("ran out of disk space while writing {}", path.display());
+    }
+    Err(err).with_path_context(path)
+}
+
 pub trait GenericDeserializer<T> {
     fn deserialize_slice(&self, data: &[u8]) -> anyhow::Result<T> {
         self.deserialize_reader(Cursor::new(data))
@@ -237,14 +915,20 @@ pub trait GenericDeserializer<T> {
         R: Read;
 }
 
-pub struct IgnoreDeserializer;
+pub struct JarDeserializer;
+
+impl GenericDeserializer<()> for JarDeserializer {
+    fn deserialize_slice(&self, data: &[u8]) -> anyhow::Result<()> {
+        check_jar(Cursor::new(data))
+    }
 
-impl GenericDeserializer<()> for IgnoreDeserializer {
-    fn deserialize_reader<R>(&self, _data: R) -> anyhow::Result<()>
+    fn deserialize_reader<R>(&self, mut data: R) -> anyhow::Result<()>
     where
         R: Read,
     {
-        Ok(())
+        let mut bytes = Vec::new();
+        data.read_to_end(&mut bytes)?;
+        self.deserialize_slice(&bytes)
     }
 }
 
@@ -275,3 +959,85 @@ where
         Ok(serde_json::from_reader(data)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn not_found_errors() {
+        assert!(is_not_found(&io::Error::from(io::ErrorKind::NotFound)));
+        assert!(!is_not_found(&io::Error::from(
+            io::ErrorKind::PermissionDenied
+        )));
+    }
+
+    #[test]
+    fn not_a_directory_is_not_found() {
+        #[cfg(unix)]
+        assert!(is_not_found(&io::Error::from_raw_os_error(20)));
+        #[cfg(windows)]
+        assert!(is_not_found(&io::Error::from_raw_os_error(267)));
+
+        // a path going through a file fails with the platform's not-a-directory error
+        let dir = TempDir::new("is_not_found").unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        let err = fs::read(file.join("child")).unwrap_err();
+        assert!(is_not_found(&err));
+    }
+
+    fn make_jar() -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        writer
+            .start_file(
+                "META-INF/MANIFEST.MF",
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(b"Manifest-Version: 1.0\r\n").unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn complete_jar_is_accepted() {
+        assert!(check_jar(io::Cursor::new(make_jar())).is_ok());
+    }
+
+    #[test]
+    fn truncated_jar_is_rejected() {
+        let mut jar = make_jar();
+        // cut off the central directory, keeping the local file header
+        jar.truncate(jar.len() / 2);
+        assert!(jar.starts_with(ZIP_MAGIC));
+        assert!(check_jar(io::Cursor::new(jar)).is_err());
+
+        assert!(check_jar(io::Cursor::new(ZIP_MAGIC.to_vec())).is_err());
+    }
+
+    #[test]
+    fn html_is_rejected() {
+        let html = b"<!DOCTYPE html><html></html>".to_vec();
+        assert!(check_jar(io::Cursor::new(html)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn copy_directory_rejects_symlink_loop() {
+        let src = TempDir::new("copy_src").unwrap();
+        fs::write(src.path().join("file"), "").unwrap();
+        std::os::unix::fs::symlink(".", src.path().join("loop")).unwrap();
+        let dst = TempDir::new("copy_dst").unwrap();
+        assert!(copy_directory(src.path(), dst.path().join("copy")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hash_template_rejects_symlink_loop() {
+        let src = TempDir::new("hash_src").unwrap();
+        fs::write(src.path().join("file"), "").unwrap();
+        std::os::unix::fs::symlink(".", src.path().join("loop")).unwrap();
+        assert!(hash_template(src.path()).is_err());
+    }
+}
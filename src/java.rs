@@ -1,16 +1,25 @@
-use crate::{ioutil, ContextExt};
+use crate::output::{verbose, verbosity, Verbosity};
+use crate::{ioutil, ContextExt, LINE_ENDING};
 use anyhow::{bail, Context};
+use clap::ValueEnum;
 use std::cmp::Ordering;
 use std::collections::{BTreeSet, VecDeque};
 use std::ffi::OsStr;
 use std::fmt::{Display, Formatter, Write};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
 use std::{env, fs, io};
 use tempfile::TempDir;
 
+const VERIFY_TIMEOUT: Duration = Duration::from_secs(60);
+// the version check only prints a system property, a healthy jvm is done in well under a second
+const VERSION_CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+const VERIFY_OUTPUT_LINES: usize = 20;
+
 #[cfg(target_os = "windows")]
 const JAVA_EXE_NAME: &str = "javaw.exe";
 #[cfg(not(target_os = "windows"))]
@@ -703,7 +712,14 @@ fn add_javas_from_env(java_candidates: &mut Vec<PathBuf>) {
     }
 }
 
-fn get_java_version_from_release_file(java_path: &Path) -> anyhow::Result<Option<String>> {
+struct JavaRelease {
+    version: String,
+    vm: Option<JavaVm>,
+    arch: Option<JavaArch>,
+    vendor: Option<String>,
+}
+
+fn get_java_version_from_release_file(java_path: &Path) -> anyhow::Result<Option<JavaRelease>> {
     let Some(parent) = java_path.parent().and_then(|parent| parent.parent()) else {
         return Ok(None);
     };
@@ -713,16 +729,47 @@ fn get_java_version_from_release_file(java_path: &Path) -> anyhow::Result<Option
         Err(err) if ioutil::is_not_found(&err) => return Ok(None),
         Err(err) => return Err(err).with_path_context(&release_path),
     };
+    let mut version = None;
+    let mut jvm_variant = String::new();
+    let mut implementor = String::new();
+    let mut vendor = None;
+    let mut graalvm = false;
+    let mut arch = None;
     for line in BufReader::new(release_file).lines() {
         let line = line.with_path_context(&release_path)?;
-        if let Some(version) = line
-            .strip_prefix("JAVA_VERSION=\"")
-            .and_then(|version| version.strip_suffix('"'))
-        {
-            return Ok(Some(version.to_owned()));
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim_matches('"');
+        match key {
+            "JAVA_VERSION" => version = Some(value.to_owned()),
+            "JVM_VARIANT" => jvm_variant = value.to_lowercase(),
+            "IMPLEMENTOR" => {
+                implementor = value.to_lowercase();
+                vendor = Some(value.to_owned());
+            }
+            "GRAALVM_VERSION" => graalvm = true,
+            "OS_ARCH" => arch = JavaArch::from_os_arch(value),
+            _ => {}
         }
     }
-    Ok(None)
+    let Some(version) = version else {
+        return Ok(None);
+    };
+
+    let vm = if jvm_variant.contains("openj9") || implementor.contains("openj9") {
+        JavaVm::OpenJ9
+    } else if graalvm || implementor.contains("graalvm") {
+        JavaVm::GraalVm
+    } else {
+        JavaVm::HotSpot
+    };
+    Ok(Some(JavaRelease {
+        version,
+        vm: Some(vm),
+        arch,
+        vendor,
+    }))
 }
 
 fn get_java_version_from_system_property(
@@ -741,67 +788,263 @@ fn get_java_version_from_system_property(
         }
     };
 
-    let output = Command::new(java_path)
+    let mut child = Command::new(java_path)
         .arg("VersionCheck")
         .current_dir(version_check_dir.path())
-        .output()
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
         .context("java version check")?;
-    if !output.status.success() {
+
+    // a broken jvm can hang or sit on an error dialog, which would block discovery forever
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() > VERSION_CHECK_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            eprintln!(
+                "warning: {} did not finish its version check within {} seconds, skipping it",
+                java_path.display(),
+                VERSION_CHECK_TIMEOUT.as_secs()
+            );
+            bail!("{} timed out on version check", java_path.display());
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    if !status.success() {
         bail!(
             "{} returned exit code {} on version check",
             java_path.display(),
-            output.status
+            status
         )
     }
-    Ok(std::str::from_utf8(&output.stdout)?.trim().to_owned())
+    let mut stdout = String::new();
+    if let Some(mut child_stdout) = child.stdout.take() {
+        child_stdout
+            .read_to_string(&mut stdout)
+            .context("java version check")?;
+    }
+    Ok(stdout.trim().to_owned())
+}
+
+// runs the server with --help, which is quick but still loads enough to catch most problems with the jvm
+pub fn verify_java(
+    java_path: &Path,
+    jvm_args: &[String],
+    launch_args: &[String],
+    instance_path: &Path,
+) -> anyhow::Result<()> {
+    let mut output_file = tempfile::tempfile()?;
+    let mut child = Command::new(java_path)
+        .args(jvm_args)
+        .args(launch_args)
+        .arg("--help")
+        .current_dir(instance_path)
+        .stdin(Stdio::null())
+        .stdout(output_file.try_clone()?)
+        .stderr(output_file.try_clone()?)
+        .spawn()
+        .context("launching the server to verify java")?;
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if start.elapsed() > VERIFY_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+
+    let mut output = Vec::new();
+    output_file.seek(SeekFrom::Start(0))?;
+    output_file.read_to_end(&mut output)?;
+    let output = String::from_utf8_lossy(&output);
+
+    if output.contains("UnsupportedClassVersionError") {
+        bail!(
+            "{} is too old to run this server (UnsupportedClassVersionError)",
+            java_path.display()
+        );
+    }
+    if output.contains("Could not reserve enough space")
+        || output.contains("Invalid maximum heap size")
+        || output.contains("Initial heap size set to a larger value")
+    {
+        bail!("{} could not allocate the server's memory, try a smaller --max-memory or a 64-bit java", java_path.display());
+    }
+
+    let Some(status) = status else {
+        eprintln!(
+            "warning: the server did not exit within {} seconds while verifying java, assuming it works",
+            VERIFY_TIMEOUT.as_secs()
+        );
+        return Ok(());
+    };
+    if !status.success() {
+        let lines: Vec<_> = output.lines().collect();
+        let tail = &lines[lines.len().saturating_sub(VERIFY_OUTPUT_LINES)..];
+        bail!(
+            "the server exited with code {} while verifying java, output:{LINE_ENDING}{}",
+            status,
+            tail.join(LINE_ENDING)
+        );
+    }
+
+    Ok(())
 }
 
 fn get_java_version(
     java_path: &Path,
     version_check_dir: &mut Option<TempDir>,
-) -> anyhow::Result<String> {
+) -> anyhow::Result<JavaRelease> {
     match get_java_version_from_release_file(java_path) {
-        Ok(Some(version)) => Ok(version),
-        Ok(None) => get_java_version_from_system_property(java_path, version_check_dir),
+        Ok(Some(release)) => Ok(release),
+        Ok(None) => Ok(JavaRelease {
+            version: get_java_version_from_system_property(java_path, version_check_dir)?,
+            vm: None,
+            arch: None,
+            vendor: None,
+        }),
         Err(err) => Err(err),
     }
 }
 
 pub fn find_java_candidates() -> anyhow::Result<Vec<JavaCandidate>> {
     let mut version_check_dir = None;
-    find_java_paths()?
-        .into_iter()
-        .map(|path| create_java_candidate_for_path(path, &mut version_check_dir))
-        .collect::<anyhow::Result<Vec<_>>>()
+    let mut candidates = Vec::new();
+    let mut skipped = 0;
+    // one broken install shouldn't stop the user from picking a working one
+    for path in find_java_paths()? {
+        let display_path = path.display().to_string();
+        match create_java_candidate_for_path(path, &mut version_check_dir) {
+            Ok(candidate) => candidates.push(candidate),
+            Err(err) => {
+                verbose!("skipping java at {display_path}: {err:#}");
+                skipped += 1;
+            }
+        }
+    }
+    if skipped != 0 {
+        let hint = if verbosity() < Verbosity::Verbose {
+            ", use --verbose to see them"
+        } else {
+            ""
+        };
+        eprintln!(
+            "warning: skipped {skipped} java installs whose version could not be determined{hint}"
+        );
+    }
+    Ok(candidates)
 }
 
 pub fn create_java_candidate_for_path(
     path: PathBuf,
     version_check_dir: &mut Option<TempDir>,
 ) -> anyhow::Result<JavaCandidate> {
-    let version = get_java_version(&path, version_check_dir)?;
-    let version = ParsedJavaVersion::parse(&version)?;
-    Ok(JavaCandidate { path, version })
+    let release = get_java_version(&path, version_check_dir)?;
+    let version = ParsedJavaVersion::parse(&release.version)?;
+    Ok(JavaCandidate {
+        path,
+        version,
+        vm: release.vm,
+        arch: release.arch,
+        vendor: release.vendor,
+    })
 }
 
 #[derive(Debug)]
 pub struct JavaCandidate {
     pub path: PathBuf,
     pub version: ParsedJavaVersion,
+    // None if there was no release file to read it from
+    pub vm: Option<JavaVm>,
+    pub arch: Option<JavaArch>,
+    // the IMPLEMENTOR in the release file, e.g. Eclipse Adoptium
+    pub vendor: Option<String>,
+}
+
+impl JavaCandidate {
+    // some mods misbehave on OpenJ9, GraalVM is built on top of HotSpot so is fine
+    pub fn is_hotspot_based(&self) -> bool {
+        self.vm != Some(JavaVm::OpenJ9)
+    }
 }
 
 impl Display for JavaCandidate {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} ({})", self.path.display(), self.version)
+        write!(f, "{} ({}", self.path.display(), self.version)?;
+        if let Some(vm) = self.vm {
+            write!(f, " {vm}")?;
+        }
+        if let Some(arch) = self.arch {
+            write!(f, " {arch}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum JavaVm {
+    HotSpot,
+    GraalVm,
+    OpenJ9,
+}
+
+impl Display for JavaVm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            JavaVm::HotSpot => "HotSpot",
+            JavaVm::GraalVm => "GraalVM",
+            JavaVm::OpenJ9 => "OpenJ9",
+        })
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum JavaArch {
+    X64,
+    Aarch64,
+    X86,
+}
+
+impl JavaArch {
+    // the values of os.arch, which differ between vendors and platforms
+    fn from_os_arch(os_arch: &str) -> Option<JavaArch> {
+        match os_arch {
+            "x86_64" | "amd64" => Some(JavaArch::X64),
+            "aarch64" | "arm64" => Some(JavaArch::Aarch64),
+            "x86" | "i386" | "i486" | "i586" | "i686" => Some(JavaArch::X86),
+            _ => None,
+        }
+    }
+}
+
+impl Display for JavaArch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            JavaArch::X64 => "x64",
+            JavaArch::Aarch64 => "aarch64",
+            JavaArch::X86 => "x86",
+        })
+    }
+}
+
+#[derive(Debug)]
 pub struct ParsedJavaVersion {
     pub major: u32,
     minor: u32,
     security: u32,
     prerelease: String,
+    // the +13 in 21.0.2+13-LTS, vendors append information after it which is ignored
+    build: Option<u32>,
 }
 
 impl ParsedJavaVersion {
@@ -818,6 +1061,13 @@ impl ParsedJavaVersion {
                 .map(|index| index + start)
                 .unwrap_or(str.len())
         }
+        // the optional information at the end may contain dashes and dots, e.g. -Ubuntu-0ubuntu1.22.04
+        fn find_end_of_optional(str: &str, start: usize) -> usize {
+            str[start..]
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '.')
+                .map(|index| index + start)
+                .unwrap_or(str.len())
+        }
 
         fn parse_inner(
             str: &str,
@@ -855,6 +1105,24 @@ impl ParsedJavaVersion {
                 prerelease = &str[prerelease_start..pos];
             }
 
+            let mut build = None;
+            if str[pos..].starts_with('+') {
+                let build_start = pos + 1;
+                pos = find_first_non_digit(str, build_start);
+                if pos != build_start {
+                    build = Some(
+                        str[build_start..pos]
+                            .parse()
+                            .with_context(|| format!("invalid version {str}"))?,
+                    );
+                }
+            }
+
+            // e.g. the -LTS in 21.0.2+13-LTS
+            if str[pos..].starts_with('-') {
+                pos = find_end_of_optional(str, pos + 1);
+            }
+
             if pos != str.len() {
                 bail!("invalid version {str}");
             }
@@ -864,6 +1132,7 @@ impl ParsedJavaVersion {
                 minor,
                 security,
                 prerelease: prerelease.to_owned(),
+                build,
             })
         }
 
@@ -896,10 +1165,22 @@ impl Display for ParsedJavaVersion {
                 }
             }
         }
+        if let Some(build) = self.build {
+            write!(f, "+{build}")?;
+        }
         Ok(())
     }
 }
 
+// equal exactly when cmp says so, prereleases are ignored like in cmp
+impl PartialEq for ParsedJavaVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ParsedJavaVersion {}
+
 impl PartialOrd for ParsedJavaVersion {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -923,6 +1204,55 @@ impl Ord for ParsedJavaVersion {
             return cmp;
         }
 
-        Ordering::Equal
+        self.build.cmp(&other.build)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(str: &str) -> ParsedJavaVersion {
+        ParsedJavaVersion::parse(str).unwrap()
+    }
+
+    fn assert_parts(
+        version: &ParsedJavaVersion,
+        (major, minor, security): (u32, u32, u32),
+        prerelease: &str,
+        build: Option<u32>,
+    ) {
+        assert_eq!(version.major, major);
+        assert_eq!(version.minor, minor);
+        assert_eq!(version.security, security);
+        assert_eq!(version.prerelease, prerelease);
+        assert_eq!(version.build, build);
+    }
+
+    #[test]
+    fn parse_major_only() {
+        assert_parts(&parse("21+35"), (21, 0, 0), "", Some(35));
+    }
+
+    #[test]
+    fn parse_security() {
+        assert_parts(&parse("17.0.9+9"), (17, 0, 9), "", Some(9));
+    }
+
+    #[test]
+    fn parse_old_format() {
+        assert_parts(&parse("1.8.0_392-b08"), (8, 0, 392), "b08", None);
+    }
+
+    #[test]
+    fn parse_vendor_suffix() {
+        assert_parts(&parse("21.0.2+13-LTS"), (21, 0, 2), "", Some(13));
+    }
+
+    #[test]
+    fn build_is_compared() {
+        assert!(parse("21.0.2+13") > parse("21.0.2+12"));
+        assert_ne!(parse("21.0.2+13"), parse("21.0.2+12"));
+        assert_eq!(parse("21.0.2+13-LTS"), parse("21.0.2+13"));
     }
 }
@@ -1,25 +1,49 @@
-use crate::cli::{Cli, Command};
+use crate::cli::{Cli, Command, ErrorFormat, JavaSubcommand, ProgressFormat};
 use crate::commands::add::add_mod;
+use crate::commands::backup::backup_instance;
+use crate::commands::clean::clean_cache;
+use crate::commands::export::export_instance;
+use crate::commands::import::import_instance;
+use crate::commands::java::list_java;
 use crate::commands::new::make_new_instance;
-use anyhow::Context;
+use crate::commands::prefetch::prefetch_versions;
+use crate::commands::regen::regenerate_script;
+use crate::ioutil::CacheOptions;
+use crate::mod_provider::modrinth;
+use crate::output::{status, verbose, Verbosity};
+use crate::server_config::ServerConfig;
 use clap::{crate_name, crate_version, Parser};
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
 use reqwest::blocking::Client;
 use std::borrow::Cow;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, Ipv4Addr};
 use std::path::{Path, PathBuf};
-use std::{env, fs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::{env, fs, process};
 
 mod cli;
 mod commands;
 mod hashing;
+mod install_manifest;
 mod instance;
 mod ioutil;
 mod java;
+mod memory;
+mod mirror;
 mod mod_loader;
 mod mod_provider;
 mod mojang;
+mod output;
+mod properties;
+mod server_config;
+mod template;
 
 const CACHE_DIR: &str = concat!(".", crate_name!(), "_cache");
+const DEFAULT_CONFIG_TEMPLATE_DIR: &str = "default-config-template";
 
 #[cfg(target_os = "windows")]
 const RUN_SERVER_FILENAME: &str = "run_server.bat";
@@ -32,47 +56,232 @@ const LINE_ENDING: &str = "\r\n";
 const LINE_ENDING: &str = "\n";
 
 fn main() {
-    if let Err(err) = do_main() {
-        #[cfg(feature = "dev")]
-        eprintln!("{} error: {:#?}", crate_name!(), err);
-        #[cfg(not(feature = "dev"))]
-        {
-            let mut chain = err.chain();
-            eprintln!("{} error: {}", crate_name!(), chain.next().unwrap());
-            for err in chain {
-                eprintln!("caused by: {err}");
-            }
+    let cli = Cli::parse();
+    let error_format = cli.error_format;
+    if let Err(err) = do_main(cli) {
+        match error_format {
+            ErrorFormat::Human => print_human_error(&err),
+            ErrorFormat::Json => print_json_error(&err),
         }
+        process::exit(1);
     }
 }
 
-fn do_main() -> anyhow::Result<()> {
-    let cache_dir = get_cache_dir();
+fn print_human_error(err: &anyhow::Error) {
+    #[cfg(feature = "dev")]
+    eprintln!("{} error: {:#?}", crate_name!(), err);
+    #[cfg(not(feature = "dev"))]
+    {
+        let mut chain = err.chain();
+        eprintln!("{} error: {}", crate_name!(), chain.next().unwrap());
+        for err in chain {
+            eprintln!("caused by: {err}");
+        }
+    }
+}
+
+fn print_json_error(err: &anyhow::Error) {
+    let chain: Vec<_> = err
+        .chain()
+        .map(|err| match err.downcast_ref::<PathError>() {
+            Some(path_error) => serde_json::json!({ "path": path_error.path }),
+            None => serde_json::json!({ "message": err.to_string() }),
+        })
+        .collect();
+    let json = serde_json::json!({
+        "exit_code": 1,
+        "message": err.to_string(),
+        "chain": chain,
+    });
+    eprintln!("{json}");
+}
+
+fn do_main(cli: Cli) -> anyhow::Result<()> {
+    let cache_dir = cli.cache_dir.clone().unwrap_or_else(get_cache_dir);
     fs::create_dir_all(&cache_dir)?;
 
-    let cli = Cli::parse();
+    let mut cli = cli;
+    if let Command::New(command) = &mut cli.command {
+        if let Some(config) = command.config.clone() {
+            ServerConfig::load(&config)?.apply(command, &config)?;
+        }
+    }
     cli.validate()?;
 
+    ioutil::set_cache_options(CacheOptions {
+        refresh: cli.refresh,
+        ttl: Duration::from_secs(cli.cache_ttl.saturating_mul(60)),
+    });
+
+    if let Some(max_download_speed) = cli.max_download_speed {
+        ioutil::set_max_download_speed(max_download_speed);
+    }
+
+    if let Some(mojang_mirror) = cli.mojang_mirror.clone() {
+        mirror::set_mojang_mirror(mojang_mirror)?;
+    }
+
+    cli::set_no_tui(cli.no_tui);
+    cli::set_accept_all_prompts(cli.accept_all_prompts);
+    output::set_no_progress(cli.no_progress);
+    output::set_json_progress(cli.progress_format == ProgressFormat::Json);
+    set_user_agent(cli.user_agent.as_deref(), cli.replace_user_agent);
+    IPV4_ONLY.store(cli.ipv4_only, Ordering::Relaxed);
+
+    if cli.quiet {
+        output::set_verbosity(Verbosity::Quiet);
+    } else if cli.verbose {
+        output::set_verbosity(Verbosity::Verbose);
+    }
+
+    if let Some(fabric_meta_url) = &cli.fabric_meta_url {
+        mirror::set_fabric_meta_url(fabric_meta_url);
+        verbose!("using fabric meta at {}", mirror::fabric_meta_url());
+    }
+    if let Some(paper_api_url) = &cli.paper_api_url {
+        mirror::set_paper_api_url(paper_api_url);
+        verbose!("using paper api at {}", mirror::paper_api_url());
+    }
+
     match cli.command {
         Command::Add(command) => add_mod(command, cache_dir),
-        Command::New(command) => make_new_instance(command, cache_dir),
+        Command::Backup(command) => backup_instance(command),
+        Command::CleanCache(command) => clean_cache(command, cache_dir),
+        Command::Export(command) => export_instance(command),
+        Command::Import(command) => import_instance(command),
+        Command::Java(command) => match command.command {
+            JavaSubcommand::List(command) => list_java(command),
+        },
+        Command::New(command) => make_new_instance(*command, cache_dir),
+        Command::Prefetch(command) => prefetch_versions(command, cache_dir),
+        Command::RegenScript(command) => regenerate_script(command),
+        Command::Search(command) => modrinth::search(&command),
     }
 }
 
+const DEFAULT_USER_AGENT: &str = concat!(
+    crate_name!(),
+    " ",
+    crate_version!(),
+    " (",
+    env!("GIT_URL"),
+    ")"
+);
+
+static USER_AGENT: OnceLock<String> = OnceLock::new();
+static IPV4_ONLY: AtomicBool = AtomicBool::new(false);
+
+fn set_user_agent(user_agent: Option<&str>, replace: bool) {
+    let user_agent = match user_agent {
+        Some(user_agent) if replace => user_agent.to_owned(),
+        Some(user_agent) => format!("{DEFAULT_USER_AGENT} {user_agent}"),
+        None => return,
+    };
+    let _ = USER_AGENT.set(user_agent);
+}
+
 fn make_client() -> anyhow::Result<Client> {
-    Ok(Client::builder()
-        .user_agent(concat!(
-            crate_name!(),
-            " ",
-            crate_version!(),
-            " (",
-            env!("GIT_URL"),
-            ")"
-        ))
-        .build()?)
-}
-
-fn make_progress_bar(len: u64, message: impl Into<Cow<'static, str>>) -> ProgressBar {
+    let user_agent = USER_AGENT
+        .get()
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_USER_AGENT);
+    let mut builder = Client::builder().user_agent(user_agent);
+    // binding to an ipv4 address makes the connector skip ipv6 addresses entirely
+    if IPV4_ONLY.load(Ordering::Relaxed) {
+        builder = builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    }
+    Ok(builder.build()?)
+}
+
+// a progress bar, or json progress events with --progress-format json
+struct Progress {
+    bar: ProgressBar,
+    json: Option<JsonProgress>,
+}
+
+struct JsonProgress {
+    file: String,
+    total: u64,
+    // progress is reported every 8K, only print an event every percent
+    last_reported: AtomicU64,
+    finished: AtomicBool,
+}
+
+// a download that fails returns early with ?, so this is the only place that sees it
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(json) = &self.json {
+            if !json.finished.load(Ordering::Relaxed) {
+                print_progress_event(serde_json::json!({
+                    "event": "download_error",
+                    "file": json.file,
+                    "downloaded": json.last_reported.load(Ordering::Relaxed),
+                    "total": json.total,
+                }));
+            }
+        }
+    }
+}
+
+impl Progress {
+    fn set_position(&self, downloaded: u64) {
+        let Some(json) = &self.json else {
+            self.bar.set_position(downloaded);
+            return;
+        };
+        let last_reported = json.last_reported.load(Ordering::Relaxed);
+        if downloaded < json.total && downloaded.saturating_sub(last_reported) < json.total / 100 {
+            return;
+        }
+        json.last_reported.store(downloaded, Ordering::Relaxed);
+        print_progress_event(serde_json::json!({
+            "event": "download",
+            "file": json.file,
+            "downloaded": downloaded,
+            "total": json.total,
+        }));
+    }
+}
+
+fn print_progress_event(event: serde_json::Value) {
+    eprintln!("{event}");
+}
+
+fn make_progress_bar(len: u64, file: &Path, message: impl Into<Cow<'static, str>>) -> Progress {
+    if output::json_progress() {
+        let file = file
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        print_progress_event(serde_json::json!({
+            "event": "download_start",
+            "file": file,
+            "total": len,
+        }));
+        return Progress {
+            bar: ProgressBar::hidden(),
+            json: Some(JsonProgress {
+                file,
+                total: len,
+                last_reported: AtomicU64::new(0),
+                finished: AtomicBool::new(false),
+            }),
+        };
+    }
+    if output::verbosity() == Verbosity::Quiet {
+        return Progress {
+            bar: ProgressBar::hidden(),
+            json: None,
+        };
+    }
+    if !output::progress_bars_enabled() {
+        status!("{} ({})", message.into(), HumanBytes(len));
+        return Progress {
+            bar: ProgressBar::hidden(),
+            json: None,
+        };
+    }
+
     let pb = ProgressBar::new(len).with_message(message);
     pb.set_style(
         ProgressStyle::default_bar()
@@ -80,7 +289,45 @@ fn make_progress_bar(len: u64, message: impl Into<Cow<'static, str>>) -> Progres
             .unwrap()
             .progress_chars("##-"),
     );
-    pb
+    Progress {
+        bar: pb,
+        json: None,
+    }
+}
+
+// hidden bars print nothing when finished, so say that it's done instead
+fn finish_progress_bar(pb: &Progress, message: impl Into<Cow<'static, str>>) {
+    let message = message.into();
+    if let Some(json) = &pb.json {
+        json.finished.store(true, Ordering::Relaxed);
+        print_progress_event(serde_json::json!({
+            "event": "download_finish",
+            "file": json.file,
+            "total": json.total,
+        }));
+        return;
+    }
+    if pb.bar.is_hidden() {
+        status!("{message}");
+    }
+    pb.bar.finish_with_message(message);
+}
+
+// for downloads that run at the same time, so that their bars don't draw over each other
+fn add_progress_bar(
+    multi_progress: &MultiProgress,
+    len: u64,
+    file: &Path,
+    message: impl Into<Cow<'static, str>>,
+) -> Progress {
+    let pb = make_progress_bar(len, file, message);
+    if pb.bar.is_hidden() {
+        return pb;
+    }
+    Progress {
+        bar: multi_progress.add(pb.bar.clone()),
+        json: None,
+    }
 }
 
 #[cfg(feature = "dev")]
@@ -107,9 +354,34 @@ pub trait ContextExt<T> {
 
 impl<T, E> ContextExt<T> for Result<T, E>
 where
-    Result<T, E>: Context<T, E>,
+    E: Into<anyhow::Error>,
 {
     fn with_path_context(self, path: &Path) -> anyhow::Result<T> {
-        self.with_context(|| path.display().to_string())
+        self.map_err(|err| {
+            PathError {
+                path: path.to_owned(),
+                source: err.into(),
+            }
+            .into()
+        })
+    }
+}
+
+// a separate error type rather than plain context so that the path can be picked out of the error chain
+#[derive(Debug)]
+struct PathError {
+    path: PathBuf,
+    source: anyhow::Error,
+}
+
+impl Display for PathError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.path.display(), f)
+    }
+}
+
+impl Error for PathError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.source)
     }
 }
@@ -0,0 +1,169 @@
+use crate::output::status;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+const MIN_AUTO_MEMORY_MIB: u64 = 1024;
+const MAX_AUTO_MEMORY_MIB: u64 = 16 * 1024;
+
+pub fn parse_memory_size(str: &str) -> anyhow::Result<u64> {
+    let bytes = parse_byte_size(str, "memory size")?;
+    let mib = bytes.div_ceil(1 << 20);
+    if mib == 0 {
+        bail!("memory size {str} is too small");
+    }
+    Ok(mib)
+}
+
+// sizes like 512, 500K or 4G, what is the name of the value for error messages
+pub fn parse_byte_size(str: &str, what: &str) -> anyhow::Result<u64> {
+    let (digits, unit) = match str.as_bytes().last() {
+        Some(b'k' | b'K') => (&str[..str.len() - 1], 1 << 10),
+        Some(b'm' | b'M') => (&str[..str.len() - 1], 1 << 20),
+        Some(b'g' | b'G') => (&str[..str.len() - 1], 1 << 30),
+        Some(b't' | b'T') => (&str[..str.len() - 1], 1 << 40),
+        _ => (str, 1),
+    };
+    let value: u64 = digits
+        .parse()
+        .with_context(|| format!("invalid {what} {str}"))?;
+    let Some(bytes) = value.checked_mul(unit) else {
+        bail!("{what} {str} is too large");
+    };
+    Ok(bytes)
+}
+
+pub fn auto_memory_mib() -> anyhow::Result<u64> {
+    let Some(total) = total_system_memory()? else {
+        bail!("could not determine the amount of system memory, specify --max-memory instead");
+    };
+    let total_mib = total / (1024 * 1024);
+    Ok((total_mib / 2).clamp(MIN_AUTO_MEMORY_MIB, MAX_AUTO_MEMORY_MIB))
+}
+
+#[cfg(target_os = "windows")]
+#[allow(non_snake_case, non_camel_case_types, clippy::upper_case_acronyms)]
+fn total_system_memory() -> anyhow::Result<Option<u64>> {
+    use std::{io, mem};
+
+    #[repr(C)]
+    struct MEMORYSTATUSEX {
+        dwLength: u32,
+        dwMemoryLoad: u32,
+        ullTotalPhys: u64,
+        ullAvailPhys: u64,
+        ullTotalPageFile: u64,
+        ullAvailPageFile: u64,
+        ullTotalVirtual: u64,
+        ullAvailVirtual: u64,
+        ullAvailExtendedVirtual: u64,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GlobalMemoryStatusEx(lpBuffer: *mut MEMORYSTATUSEX) -> i32;
+    }
+
+    let mut status: MEMORYSTATUSEX = unsafe { mem::zeroed() };
+    status.dwLength = mem::size_of::<MEMORYSTATUSEX>() as u32;
+    if unsafe { GlobalMemoryStatusEx(&mut status) } == 0 {
+        return Err(io::Error::last_os_error()).context("GlobalMemoryStatusEx");
+    }
+    Ok(Some(status.ullTotalPhys))
+}
+
+#[cfg(target_os = "macos")]
+fn total_system_memory() -> anyhow::Result<Option<u64>> {
+    use std::ffi::{c_char, c_int, c_void};
+    use std::{io, mem, ptr};
+
+    extern "C" {
+        fn sysctlbyname(
+            name: *const c_char,
+            oldp: *mut c_void,
+            oldlenp: *mut usize,
+            newp: *mut c_void,
+            newlen: usize,
+        ) -> c_int;
+    }
+
+    let mut memsize: u64 = 0;
+    let mut len = mem::size_of::<u64>();
+    let result = unsafe {
+        sysctlbyname(
+            c"hw.memsize".as_ptr(),
+            &mut memsize as *mut u64 as *mut c_void,
+            &mut len,
+            ptr::null_mut(),
+            0,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error()).context("sysctl hw.memsize");
+    }
+    Ok(Some(memsize))
+}
+
+#[cfg(target_os = "linux")]
+fn total_system_memory() -> anyhow::Result<Option<u64>> {
+    use crate::ContextExt;
+    use std::fs;
+    use std::path::Path;
+
+    let meminfo_path = Path::new("/proc/meminfo");
+    let meminfo = match fs::read_to_string(meminfo_path) {
+        Ok(meminfo) => meminfo,
+        Err(err) if crate::ioutil::is_not_found(&err) => return Ok(None),
+        Err(err) => return Err(err).with_path_context(meminfo_path),
+    };
+    for line in meminfo.lines() {
+        if let Some(total) = line
+            .strip_prefix("MemTotal:")
+            .and_then(|total| total.trim().strip_suffix("kB"))
+        {
+            let total: u64 = total.trim().parse().with_path_context(meminfo_path)?;
+            return Ok(Some(total * 1024));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn total_system_memory() -> anyhow::Result<Option<u64>> {
+    Ok(None)
+}
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+pub struct JvmMemory {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_mib: Option<u64>,
+    pub max_mib: u64,
+}
+
+impl JvmMemory {
+    pub fn from_options(
+        memory_auto: bool,
+        max_memory: Option<u64>,
+    ) -> anyhow::Result<Option<JvmMemory>> {
+        if memory_auto {
+            let memory = auto_memory_mib()?;
+            status!("allocating {memory}M of memory to the server");
+            Ok(Some(JvmMemory {
+                initial_mib: Some(memory),
+                max_mib: memory,
+            }))
+        } else {
+            Ok(max_memory.map(|max_mib| JvmMemory {
+                initial_mib: None,
+                max_mib,
+            }))
+        }
+    }
+
+    pub fn push_jvm_args(&self, jvm_args: &mut Vec<String>) {
+        if let Some(initial_mib) = self.initial_mib {
+            jvm_args.push(format!("-Xms{initial_mib}M"));
+        }
+        jvm_args.push(format!("-Xmx{}M", self.max_mib));
+    }
+}
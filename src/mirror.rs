@@ -0,0 +1,121 @@
+use anyhow::bail;
+use reqwest::blocking::RequestBuilder;
+use std::sync::OnceLock;
+use url::Url;
+
+const MOJANG_HOSTS: &[&str] = &[
+    "piston-meta.mojang.com",
+    "piston-data.mojang.com",
+    "launchermeta.mojang.com",
+    "launcher.mojang.com",
+];
+
+const FABRIC_META_URL: &str = "https://meta.fabricmc.net";
+const PAPER_API_URL: &str = "https://api.papermc.io";
+
+static MOJANG_MIRROR: OnceLock<Mirror> = OnceLock::new();
+static FABRIC_META_OVERRIDE: OnceLock<String> = OnceLock::new();
+static PAPER_API_OVERRIDE: OnceLock<String> = OnceLock::new();
+
+#[derive(Debug)]
+struct Mirror {
+    // never contains credentials, so that it's safe to print urls derived from it
+    base: Url,
+    credentials: Option<(String, String)>,
+}
+
+pub fn set_mojang_mirror(mut url: Url) -> anyhow::Result<()> {
+    if url.cannot_be_a_base() || url.host().is_none() {
+        bail!("invalid mirror url {url}");
+    }
+
+    let credentials = if url.username().is_empty() && url.password().is_none() {
+        None
+    } else {
+        let username = urlencoding::decode(url.username())?.into_owned();
+        let password = urlencoding::decode(url.password().unwrap_or_default())?.into_owned();
+        Some((username, password))
+    };
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+
+    let _ = MOJANG_MIRROR.set(Mirror {
+        base: url,
+        credentials,
+    });
+    Ok(())
+}
+
+// points a url at one of mojang's servers at the mirror instead, keeping the path
+pub fn mojang_url(url: &Url) -> Url {
+    let Some(mirror) = MOJANG_MIRROR.get() else {
+        return url.clone();
+    };
+    if !url
+        .host_str()
+        .is_some_and(|host| MOJANG_HOSTS.contains(&host))
+    {
+        return url.clone();
+    }
+
+    let mut mirrored = mirror.base.clone();
+    let path = format!("{}{}", mirror.base.path().trim_end_matches('/'), url.path());
+    mirrored.set_path(&path);
+    mirrored.set_query(url.query());
+    mirrored
+}
+
+// for --fabric-meta-url and --paper-api-url, which replace the scheme, host and start of the path
+pub fn parse_service_url(str: &str) -> anyhow::Result<Url> {
+    let url = Url::parse(str)?;
+    // these urls end up in verbose output, errors and the instance manifest. Checked first so the errors below can't print them
+    if !url.username().is_empty() || url.password().is_some() {
+        bail!(
+            "the url for {} should not contain a username or password",
+            url.host_str().unwrap_or_default()
+        );
+    }
+    if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+        bail!("{url} is not an http or https url");
+    }
+    if url.query().is_some() || url.fragment().is_some() {
+        bail!("{url} should be a base url without a query or fragment");
+    }
+    Ok(url)
+}
+
+pub fn set_fabric_meta_url(url: &Url) {
+    let _ = FABRIC_META_OVERRIDE.set(url.as_str().trim_end_matches('/').to_owned());
+}
+
+pub fn set_paper_api_url(url: &Url) {
+    let _ = PAPER_API_OVERRIDE.set(url.as_str().trim_end_matches('/').to_owned());
+}
+
+// the base that fabric meta paths such as /v2/versions/installer are appended to
+pub fn fabric_meta_url() -> &'static str {
+    FABRIC_META_OVERRIDE
+        .get()
+        .map_or(FABRIC_META_URL, String::as_str)
+}
+
+// the base that paper api paths such as /v2/projects/paper are appended to
+pub fn paper_api_url() -> &'static str {
+    PAPER_API_OVERRIDE
+        .get()
+        .map_or(PAPER_API_URL, String::as_str)
+}
+
+// credentials are only ever sent to the mirror itself
+pub fn authorize(request: RequestBuilder, url: &Url) -> RequestBuilder {
+    let Some(mirror) = MOJANG_MIRROR.get() else {
+        return request;
+    };
+    let Some((username, password)) = &mirror.credentials else {
+        return request;
+    };
+    if url.origin() != mirror.base.origin() {
+        return request;
+    }
+    request.basic_auth(username, Some(password))
+}
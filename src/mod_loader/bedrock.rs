@@ -0,0 +1,159 @@
+use crate::cli::NewCommand;
+use crate::commands::new::write_run_script;
+use crate::ioutil::JsonDeserializer;
+use crate::mod_loader::vanilla::ask_eula;
+use crate::output::{status, verbose};
+use crate::{finish_progress_bar, ioutil, make_progress_bar, ContextExt, LINE_ENDING};
+use anyhow::{anyhow, bail};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::fs;
+use std::path::Path;
+use url::Url;
+
+const DOWNLOAD_LINKS_URL: &str =
+    "https://net-secondary.web.minecraft-services.net/api/v1.0/download/links";
+
+#[cfg(target_os = "windows")]
+const PLATFORM: Option<(&str, &str)> = Some(("serverBedrockWindows", "bin-win"));
+#[cfg(target_os = "linux")]
+const PLATFORM: Option<(&str, &str)> = Some(("serverBedrockLinux", "bin-linux"));
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+const PLATFORM: Option<(&str, &str)> = None;
+
+// returns the bedrock version that was installed
+pub fn install_bedrock(
+    command: &NewCommand,
+    client: &Client,
+    cache_dir: &Path,
+    instance_path: &Path,
+) -> anyhow::Result<String> {
+    let Some((download_type, url_platform)) = PLATFORM else {
+        bail!("the bedrock dedicated server is only available for windows and linux");
+    };
+
+    // the download page makes you agree before downloading the server, and there's no eula.txt to write to later
+    if ask_eula(command)? != Some(true) {
+        bail!("you must agree to the Minecraft EULA and Privacy Policy to download the bedrock dedicated server");
+    }
+
+    let bedrock_cache_dir = cache_dir.join("bedrock");
+    fs::create_dir_all(&bedrock_cache_dir).with_path_context(&bedrock_cache_dir)?;
+
+    let (version, url) = match &command.version {
+        Some(version) => (
+            version.clone(),
+            format!(
+                "https://www.minecraft.net/bedrockdedicatedserver/{url_platform}/bedrock-server-{version}.zip"
+            ),
+        ),
+        None => {
+            status!("fetching bedrock versions");
+            let links: DownloadLinks = ioutil::download_with_etag(
+                client,
+                DOWNLOAD_LINKS_URL,
+                &bedrock_cache_dir.join("download_links.json"),
+                JsonDeserializer::new(),
+            )?;
+            let Some(link) = links
+                .result
+                .links
+                .into_iter()
+                .find(|link| link.download_type == download_type)
+            else {
+                bail!("could not find a bedrock server download for this platform");
+            };
+            let version = link
+                .download_url
+                .rsplit('/')
+                .next()
+                .and_then(|file_name| file_name.strip_prefix("bedrock-server-"))
+                .and_then(|file_name| file_name.strip_suffix(".zip"))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "could not determine the bedrock version from {}",
+                        link.download_url
+                    )
+                })?
+                .to_owned();
+            (version, link.download_url)
+        }
+    };
+
+    verbose!("using bedrock version {version}");
+    let zip_path = bedrock_cache_dir.join(format!("bedrock-server-{version}-{url_platform}.zip"));
+    if zip_path.exists() {
+        verbose!("using cached {}", zip_path.display());
+        ioutil::record_download(&Url::parse(&url)?, &zip_path, None)?;
+    } else {
+        let pb = RefCell::new(None);
+        let result = ioutil::download_large(
+            client,
+            &url,
+            &zip_path,
+            |download_size| {
+                if let Some(download_size) = download_size {
+                    *pb.borrow_mut() = Some(make_progress_bar(
+                        download_size,
+                        &zip_path,
+                        "downloading bedrock server",
+                    ));
+                } else {
+                    status!("downloading bedrock server");
+                }
+            },
+            |progress| {
+                if let Some(pb) = &*pb.borrow() {
+                    pb.set_position(progress);
+                }
+            },
+        )
+        .and_then(|()| ioutil::validate_jar(&zip_path));
+        if let Err(err) = result {
+            // don't leave a broken zip in the cache to be picked up next time
+            let _ = fs::remove_file(&zip_path);
+            return Err(err);
+        }
+        if let Some(pb) = pb.into_inner() {
+            finish_progress_bar(&pb, "downloaded bedrock server");
+        }
+    }
+
+    status!("extracting bedrock server");
+    fs::create_dir_all(instance_path).with_path_context(instance_path)?;
+    ioutil::extract_zip(&zip_path, instance_path)?;
+
+    if !command.jar_only {
+        write_run_script(instance_path, &launch_command())?;
+    }
+
+    Ok(version)
+}
+
+#[cfg(target_os = "windows")]
+pub fn launch_command() -> String {
+    format!("bedrock_server.exe{LINE_ENDING}")
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn launch_command() -> String {
+    format!("LD_LIBRARY_PATH=. ./bedrock_server{LINE_ENDING}")
+}
+
+#[derive(Debug, Deserialize)]
+struct DownloadLinks {
+    result: DownloadLinksResult,
+}
+
+#[derive(Debug, Deserialize)]
+struct DownloadLinksResult {
+    links: Vec<DownloadLink>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadLink {
+    download_type: String,
+    download_url: String,
+}
@@ -0,0 +1,270 @@
+use crate::ioutil::is_not_found;
+use crate::mod_loader::paper::find_mojang_jar_name;
+use crate::mod_loader::ModLoader;
+use crate::output::verbose;
+use crate::ContextExt;
+use serde::Deserialize;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+use zip::ZipArchive;
+
+const FABRIC_LAUNCH_JARS: &[&str] = &["fabric-server-launch.jar", "fabric-server-launcher.jar"];
+const BEDROCK_EXECUTABLES: &[&str] = &["bedrock_server", "bedrock_server.exe"];
+
+#[derive(Debug)]
+pub struct DetectedServer {
+    pub loader: ModLoader,
+    pub minecraft_version: Option<String>,
+    // the fabric loader version or paper build
+    pub loader_version: Option<String>,
+    // the jar the server is started from, None for bedrock
+    pub server_jar_name: Option<String>,
+}
+
+// looks for the files each loader leaves in the server folder, returns None if nothing was recognized
+pub fn detect_server(dir: &Path) -> anyhow::Result<Option<DetectedServer>> {
+    let mut file_names = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if is_not_found(&err) => return Ok(None),
+        Err(err) => return Err(err).with_path_context(dir),
+    };
+    for entry in entries {
+        let entry = entry.with_path_context(dir)?;
+        if let Some(file_name) = entry.file_name().to_str() {
+            file_names.push(file_name.to_owned());
+        }
+    }
+    file_names.sort();
+    let has_file = |name: &str| file_names.iter().any(|file_name| file_name == name);
+
+    if BEDROCK_EXECUTABLES.iter().any(|name| has_file(name)) {
+        return Ok(Some(DetectedServer {
+            loader: ModLoader::Bedrock,
+            minecraft_version: None,
+            loader_version: None,
+            server_jar_name: None,
+        }));
+    }
+
+    if let Some(launch_jar) = FABRIC_LAUNCH_JARS.iter().find(|name| has_file(name)) {
+        return Ok(Some(DetectedServer {
+            loader: ModLoader::Fabric,
+            minecraft_version: read_jar_version(&dir.join("server.jar")),
+            loader_version: find_fabric_loader_version(dir),
+            server_jar_name: Some((*launch_jar).to_owned()),
+        }));
+    }
+
+    // paper-<minecraft version>-<build>.jar is what the paper downloads page gives you
+    let paper_jar = if has_file("paperclip.jar") {
+        Some("paperclip.jar")
+    } else {
+        versioned_jar(&file_names, "paper-")
+    };
+    if let Some(paper_jar) = paper_jar {
+        let (mut minecraft_version, mut build) = match version_from_file_name(paper_jar, "paper-") {
+            Some(version) => match version.rsplit_once('-') {
+                Some((minecraft_version, build)) => {
+                    (Some(minecraft_version.to_owned()), Some(build.to_owned()))
+                }
+                None => (Some(version.to_owned()), None),
+            },
+            None => (None, None),
+        };
+        if let Some((history_version, history_build)) = read_paper_version_history(dir) {
+            minecraft_version = Some(history_version);
+            build = Some(history_build);
+        }
+        if minecraft_version.is_none() {
+            minecraft_version = find_mojang_jar_name(&dir.join(paper_jar))
+                .ok()
+                .flatten()
+                .and_then(|name| version_from_file_name(&name, "mojang_").map(ToOwned::to_owned));
+        }
+        return Ok(Some(DetectedServer {
+            loader: ModLoader::Paper,
+            minecraft_version,
+            loader_version: build,
+            server_jar_name: Some(paper_jar.to_owned()),
+        }));
+    }
+
+    let spigot_jar = if has_file("spigot.jar") {
+        Some("spigot.jar")
+    } else {
+        versioned_jar(&file_names, "spigot-")
+    };
+    if let Some(spigot_jar) = spigot_jar {
+        return Ok(Some(DetectedServer {
+            loader: ModLoader::Spigot,
+            minecraft_version: read_jar_version(&dir.join(spigot_jar))
+                .or_else(|| version_from_file_name(spigot_jar, "spigot-").map(ToOwned::to_owned)),
+            loader_version: None,
+            server_jar_name: Some(spigot_jar.to_owned()),
+        }));
+    }
+
+    let vanilla_jar = if has_file("server.jar") {
+        Some("server.jar")
+    } else {
+        versioned_jar(&file_names, "minecraft_server.")
+    };
+    if let Some(vanilla_jar) = vanilla_jar {
+        return Ok(Some(DetectedServer {
+            loader: ModLoader::Vanilla,
+            minecraft_version: read_jar_version(&dir.join(vanilla_jar)).or_else(|| {
+                version_from_file_name(vanilla_jar, "minecraft_server.").map(ToOwned::to_owned)
+            }),
+            loader_version: None,
+            server_jar_name: Some(vanilla_jar.to_owned()),
+        }));
+    }
+
+    Ok(None)
+}
+
+fn versioned_jar<'a>(file_names: &'a [String], prefix: &str) -> Option<&'a str> {
+    file_names
+        .iter()
+        .find(|name| version_from_file_name(name, prefix).is_some())
+        .map(String::as_str)
+}
+
+fn version_from_file_name<'a>(file_name: &'a str, prefix: &str) -> Option<&'a str> {
+    file_name
+        .strip_prefix(prefix)?
+        .strip_suffix(".jar")
+        .filter(|version| !version.is_empty())
+}
+
+#[derive(Debug, Deserialize)]
+struct JarVersion {
+    id: String,
+}
+
+// server jars since 1.14 contain the version json mojang generated them from
+fn read_jar_version(jar: &Path) -> Option<String> {
+    try_read_jar_version(jar).unwrap_or_else(|err| {
+        verbose!("could not read the version of {}: {err:#}", jar.display());
+        None
+    })
+}
+
+fn try_read_jar_version(jar: &Path) -> anyhow::Result<Option<String>> {
+    let file = match File::open(jar) {
+        Ok(file) => file,
+        Err(err) if is_not_found(&err) => return Ok(None),
+        Err(err) => return Err(err).with_path_context(jar),
+    };
+    let mut archive = ZipArchive::new(file).with_path_context(jar)?;
+    let version_json = match archive.by_name("version.json") {
+        Ok(version_json) => version_json,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err).with_path_context(jar),
+    };
+    let version: JarVersion = serde_json::from_reader(version_json).with_path_context(jar)?;
+    Ok(Some(version.id))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PaperVersionHistory {
+    current_version: String,
+}
+
+// paper writes this on first start
+fn read_paper_version_history(dir: &Path) -> Option<(String, String)> {
+    let contents = fs::read_to_string(dir.join("version_history.json")).ok()?;
+    let history: PaperVersionHistory = serde_json::from_str(&contents).ok()?;
+    parse_paper_version(&history.current_version)
+}
+
+// older builds write "git-Paper-496 (MC: 1.20.4)", newer ones "1.21.4-232-12d8fe0 (MC: 1.21.4)"
+fn parse_paper_version(current_version: &str) -> Option<(String, String)> {
+    let (build, minecraft_version) = current_version.split_once(" (MC: ")?;
+    let minecraft_version = minecraft_version.strip_suffix(')')?;
+    let build = match build.strip_prefix("git-Paper-") {
+        Some(build) => build,
+        None => build.strip_prefix(minecraft_version)?.strip_prefix('-')?,
+    };
+    // the commit hash that follows the build number isn't part of it
+    let build = build.split('-').next()?;
+    if build.is_empty() || !build.bytes().all(|char| char.is_ascii_digit()) {
+        return None;
+    }
+    Some((minecraft_version.to_owned(), build.to_owned()))
+}
+
+// the fabric launcher downloads the loader into the libraries folder on first start
+fn find_fabric_loader_version(dir: &Path) -> Option<String> {
+    let loader_dir = dir.join("libraries/net/fabricmc/fabric-loader");
+    let versions = fs::read_dir(loader_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok());
+    newest_fabric_loader_version(versions)
+}
+
+// compared part by part as numbers, so that 0.16.10 is newer than 0.9.3
+fn newest_fabric_loader_version(versions: impl IntoIterator<Item = String>) -> Option<String> {
+    versions.into_iter().max_by(|version1, version2| {
+        let parts = |version: &str| -> Vec<Option<u32>> {
+            version
+                .split(['.', '+', '-'])
+                .map(|part| part.parse().ok())
+                .collect()
+        };
+        parts(version1)
+            .cmp(&parts(version2))
+            .then_with(|| version1.cmp(version2))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_paper_version_format() {
+        assert_eq!(
+            parse_paper_version("git-Paper-496 (MC: 1.20.4)"),
+            Some(("1.20.4".to_owned(), "496".to_owned()))
+        );
+    }
+
+    #[test]
+    fn new_paper_version_format() {
+        assert_eq!(
+            parse_paper_version("1.21.4-232-12d8fe0 (MC: 1.21.4)"),
+            Some(("1.21.4".to_owned(), "232".to_owned()))
+        );
+    }
+
+    #[test]
+    fn unknown_paper_version_format() {
+        assert_eq!(parse_paper_version("1.21.4-12d8fe0 (MC: 1.21.4)"), None);
+        assert_eq!(parse_paper_version("git-Paper-496"), None);
+    }
+
+    fn newest(versions: &[&str]) -> Option<String> {
+        newest_fabric_loader_version(versions.iter().map(|&version| version.to_owned()))
+    }
+
+    #[test]
+    fn newest_fabric_loader_is_compared_numerically() {
+        assert_eq!(
+            newest(&["0.9.3", "0.16.10", "0.16.9"]).as_deref(),
+            Some("0.16.10")
+        );
+    }
+
+    #[test]
+    fn newest_fabric_loader_with_build_suffix() {
+        assert_eq!(
+            newest(&["0.7.10+build.191", "0.7.2+build.175"]).as_deref(),
+            Some("0.7.10+build.191")
+        );
+        assert_eq!(newest(&[]), None);
+    }
+}
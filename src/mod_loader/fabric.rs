@@ -1,92 +1,132 @@
-use crate::commands::new::{write_run_server_file, ServerInstallArgs};
-use crate::ioutil::{IgnoreDeserializer, JsonDeserializer};
-use crate::mod_loader::vanilla::{agree_to_eula, download_vanilla_server};
-use crate::{ioutil, ContextExt};
-use anyhow::{anyhow, Context};
+use crate::commands::new::{InstalledServer, ServerInstallArgs};
+use crate::ioutil::{JarDeserializer, JsonDeserializer};
+use crate::mod_loader::vanilla::download_vanilla_server;
+use crate::mod_loader::LoaderChannel;
+use crate::output::{status, verbose};
+use crate::{ioutil, mirror, ContextExt};
+use anyhow::anyhow;
+use reqwest::blocking::Client;
 use serde::Deserialize;
 use std::fs;
+use std::path::{Path, PathBuf};
 
-const INSTALLER_VERSIONS_URL: &str = "https://meta.fabricmc.net/v2/versions/installer";
+pub fn install_fabric(args: ServerInstallArgs<'_>) -> anyhow::Result<InstalledServer> {
+    let (fabric_server_launch_path, loader_version, installer_version) = download_fabric_launcher(
+        args.client,
+        args.cache_dir,
+        args.version_name,
+        args.command.fabric_loader_version.as_deref(),
+        args.command.fabric_installer_version.as_deref(),
+        args.command.loader_channel,
+    )?;
+
+    let server_jar_path = download_vanilla_server(&args)?;
+
+    fs::create_dir_all(args.instance_path).with_path_context(args.instance_path)?;
+
+    let server_jar_link_path = args.instance_path.join("server.jar");
+    args.link_jar(&server_jar_path, &server_jar_link_path)?;
 
-pub fn install_fabric(args: ServerInstallArgs<'_>) -> anyhow::Result<()> {
-    let fabric_cache_dir = args.cache_dir.join("fabric");
+    let fabric_server_launch_link_path = args
+        .instance_path
+        .join(args.server_jar_name.unwrap_or("fabric-server-launch.jar"));
+    args.link_jar(&fabric_server_launch_path, &fabric_server_launch_link_path)?;
+
+    let launch = args.launch_metadata(Vec::new());
+    let eula_accepted = args.write_launch_files(&launch)?;
+
+    Ok(InstalledServer {
+        launch,
+        eula_accepted,
+        loader_version: Some(loader_version),
+        installer_version: Some(installer_version),
+    })
+}
+
+// downloads the server launcher into the cache, returning its path and the loader version
+pub fn download_fabric_launcher(
+    client: &Client,
+    cache_dir: &Path,
+    version_name: &str,
+    loader_version: Option<&str>,
+    installer_version: Option<&str>,
+    loader_channel: LoaderChannel,
+) -> anyhow::Result<(PathBuf, String, String)> {
+    let fabric_cache_dir = cache_dir.join("fabric");
     fs::create_dir_all(&fabric_cache_dir).with_path_context(&fabric_cache_dir)?;
 
-    eprintln!("fetching fabric installer versions");
-    let installer_versions: Vec<FabricVersion> = ioutil::download_with_etag(
-        args.client,
-        INSTALLER_VERSIONS_URL,
-        &fabric_cache_dir.join("installer_versions.json"),
-        JsonDeserializer::new(),
-    )?;
-    let installer_version = first_stable(installer_versions, "installer")?;
-    let loader_version = match args.command.fabric_loader_version.as_ref() {
-        Some(loader_version) => loader_version.clone(),
+    let installer_version = match installer_version {
+        Some(installer_version) => installer_version.to_owned(),
         None => {
-            eprintln!("fetching fabric loader versions");
+            status!("fetching fabric installer versions");
+            let installer_versions: Vec<FabricVersion> = ioutil::download_with_etag(
+                client,
+                &format!("{}/v2/versions/installer", mirror::fabric_meta_url()),
+                &fabric_cache_dir.join("installer_versions.json"),
+                JsonDeserializer::new(),
+            )?;
+            first_stable(installer_versions, "installer")?
+        }
+    };
+    let loader_version = match loader_version {
+        Some(loader_version) => loader_version.to_owned(),
+        None => {
+            status!("fetching fabric loader versions");
             let loader_versions: Vec<LoaderEntry> = ioutil::download_with_etag(
-                args.client,
+                client,
                 &format!(
-                    "https://meta.fabricmc.net/v2/versions/loader/{}",
-                    urlencoding::encode(args.version_name)
+                    "{}/v2/versions/loader/{}",
+                    mirror::fabric_meta_url(),
+                    urlencoding::encode(version_name)
                 ),
-                &fabric_cache_dir.join(format!("loader_versions_{}.json", args.version_name)),
+                &fabric_cache_dir.join(format!("loader_versions_{}.json", version_name)),
                 JsonDeserializer::new(),
             )?;
-            let loader_versions = loader_versions.into_iter().map(|v| v.loader).collect();
-            first_stable(loader_versions, "loader")?
+            let loader_versions: Vec<_> = loader_versions.into_iter().map(|v| v.loader).collect();
+            match loader_channel {
+                LoaderChannel::Stable => first_stable(loader_versions, "loader")?,
+                LoaderChannel::Latest => loader_versions
+                    .into_iter()
+                    .next()
+                    .map(|version| version.version)
+                    .ok_or_else(|| {
+                        anyhow!("could not find any loader version for this Minecraft version")
+                    })?,
+            }
         }
     };
 
-    eprintln!("downloading fabric server launcher");
+    verbose!("using fabric loader {loader_version} and installer {installer_version}");
+    status!("downloading fabric server launcher");
     let fabric_server_launch_path = fabric_cache_dir.join(format!(
         "fabric-server-launch-{}-{}-{}.jar",
-        args.version_name, loader_version, installer_version
+        version_name, loader_version, installer_version
     ));
     ioutil::download_with_etag(
-        args.client,
+        client,
         &format!(
-            "https://meta.fabricmc.net/v2/versions/loader/{}/{}/{}/server/jar",
-            urlencoding::encode(args.version_name),
+            "{}/v2/versions/loader/{}/{}/{}/server/jar",
+            mirror::fabric_meta_url(),
+            urlencoding::encode(version_name),
             loader_version,
             installer_version
         ),
         &fabric_server_launch_path,
-        IgnoreDeserializer,
+        JarDeserializer,
     )?;
 
-    let server_jar_path = download_vanilla_server(&args)?;
-
-    fs::create_dir_all(args.instance_path).with_path_context(args.instance_path)?;
-
-    let server_jar_link_path = args.instance_path.join("server.jar");
-    ioutil::link_or_copy(&server_jar_path, &server_jar_link_path).with_context(|| {
-        format!(
-            "linking {} to {}",
-            server_jar_link_path.display(),
-            server_jar_path.display()
-        )
-    })?;
-
-    let fabric_server_launch_link_path = args.instance_path.join("fabric-server-launch.jar");
-    ioutil::link_or_copy(&fabric_server_launch_path, &fabric_server_launch_link_path)
-        .with_context(|| {
-            format!(
-                "linking {} to {}",
-                fabric_server_launch_link_path.display(),
-                fabric_server_launch_path.display()
-            )
-        })?;
-
-    let server_launch_command = format!(
-        "{} -Dfabric.installer.server.gameJar=server.jar -jar fabric-server-launch.jar nogui",
-        args.escaped_java_exe_name()?
-    );
-    write_run_server_file(&args, &server_launch_command)?;
-
-    agree_to_eula(&args)?;
+    Ok((fabric_server_launch_path, loader_version, installer_version))
+}
 
-    Ok(())
+pub fn launch_args(server_jar_name: Option<&str>) -> Vec<String> {
+    vec![
+        "-Dfabric.installer.server.gameJar=server.jar".to_owned(),
+        "-jar".to_owned(),
+        server_jar_name
+            .unwrap_or("fabric-server-launch.jar")
+            .to_owned(),
+        "nogui".to_owned(),
+    ]
 }
 
 #[derive(Debug, Deserialize)]
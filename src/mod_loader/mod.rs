@@ -1,16 +1,22 @@
-use crate::commands::new::ServerInstallArgs;
+use crate::commands::new::{InstalledServer, ServerInstallArgs};
 use crate::mod_loader::fabric::install_fabric;
 use crate::mod_loader::paper::install_paper;
+use crate::mod_loader::spigot::install_spigot;
 use crate::mod_loader::vanilla::install_vanilla;
 use crate::mod_provider::ModProvider;
 use crate::mojang::{ManifestVersion, Version};
+use crate::LINE_ENDING;
+use anyhow::bail;
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
 use time::macros::datetime;
 
+pub mod bedrock;
+pub mod detect;
 pub mod fabric;
 pub mod paper;
+pub mod spigot;
 pub mod vanilla;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
@@ -19,6 +25,8 @@ pub enum ModLoader {
     Vanilla,
     Fabric,
     Paper,
+    Spigot,
+    Bedrock,
 }
 
 impl ModLoader {
@@ -27,6 +35,8 @@ impl ModLoader {
             Self::Vanilla => None,
             Self::Fabric => Some(ModProvider::Modrinth),
             Self::Paper => Some(ModProvider::Hangar),
+            Self::Spigot => Some(ModProvider::Hangar),
+            Self::Bedrock => None,
         }
     }
 
@@ -35,6 +45,8 @@ impl ModLoader {
             Self::Vanilla => None,
             Self::Fabric => Some("mods"),
             Self::Paper => Some("plugins"),
+            Self::Spigot => Some("plugins"),
+            Self::Bedrock => None,
         }
     }
 
@@ -44,8 +56,11 @@ impl ModLoader {
         full_version: &Version,
     ) -> u32 {
         match self {
-            Self::Vanilla => full_version.java_version.major_version,
-            Self::Fabric => full_version.java_version.major_version.max(8),
+            Self::Vanilla => full_version.java_version.required_major_version(),
+            Self::Fabric => full_version.java_version.required_major_version().max(8),
+            Self::Spigot => full_version.java_version.required_major_version().max(8),
+            // not a java server, never called since bedrock has its own install path
+            Self::Bedrock => 0,
             Self::Paper => {
                 // TODO: un-hardcode this when Paper's web API v3 comes out
                 // TODO: these are the recommended versions, not the minimum versions
@@ -60,27 +75,68 @@ impl ModLoader {
                     16
                 } else {
                     // >=1.17
-                    full_version.java_version.major_version.max(21)
+                    full_version.java_version.required_major_version().max(21)
                 }
             }
         }
     }
 
-    pub fn install(&self, args: ServerInstallArgs<'_>) -> anyhow::Result<()> {
+    // paperclip and buildtools expect their own layout
+    pub fn supports_server_jar_name(&self) -> bool {
+        match self {
+            Self::Vanilla | Self::Fabric => true,
+            Self::Paper | Self::Spigot | Self::Bedrock => false,
+        }
+    }
+
+    // the arguments passed to java after the jvm args, none of which need escaping
+    pub fn launch_args(&self, server_jar_name: Option<&str>) -> Vec<String> {
+        match self {
+            Self::Vanilla => vanilla::launch_args(server_jar_name),
+            Self::Fabric => fabric::launch_args(server_jar_name),
+            Self::Paper => paper::launch_args(),
+            Self::Spigot => spigot::launch_args(),
+            // not launched through java
+            Self::Bedrock => Vec::new(),
+        }
+    }
+
+    // java_command is the java executable followed by any jvm args, with a trailing space
+    pub fn launch_command(&self, java_command: &str, server_jar_name: Option<&str>) -> String {
+        format!(
+            "{java_command}{}{LINE_ENDING}",
+            self.launch_args(server_jar_name).join(" ")
+        )
+    }
+
+    pub fn install(&self, args: ServerInstallArgs<'_>) -> anyhow::Result<InstalledServer> {
         match self {
             Self::Vanilla => install_vanilla(args),
             Self::Fabric => install_fabric(args),
             Self::Paper => install_paper(args),
+            Self::Spigot => install_spigot(args),
+            Self::Bedrock => {
+                bail!("bedrock servers are not installed through the java server pipeline")
+            }
         }
     }
 }
 
+// what stable means depends on how each loader's api marks its versions, see --loader-channel
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum LoaderChannel {
+    Stable,
+    Latest,
+}
+
 impl Display for ModLoader {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
             Self::Vanilla => "vanilla",
             Self::Fabric => "fabric",
             Self::Paper => "paper",
+            Self::Spigot => "spigot",
+            Self::Bedrock => "bedrock",
         })
     }
 }
@@ -1,78 +1,182 @@
-use crate::commands::new::{write_run_server_file, ServerInstallArgs};
+use crate::cli::confirm;
+use crate::commands::new::{InstalledServer, ServerInstallArgs};
 use crate::hashing::{HashAlgorithm, Sha2String};
 use crate::ioutil::JsonDeserializer;
-use crate::mod_loader::vanilla::{agree_to_eula, download_vanilla_server};
-use crate::{ioutil, make_progress_bar, ContextExt};
-use anyhow::{anyhow, bail, Context};
+use crate::mod_loader::vanilla::download_vanilla_server;
+use crate::mod_loader::LoaderChannel;
+use crate::output::{status, verbose};
+use crate::{finish_progress_bar, ioutil, make_progress_bar, mirror, ContextExt};
+use anyhow::{anyhow, bail};
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
 use serde::Deserialize;
 use std::cell::RefCell;
 use std::fs;
 use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::io::{Cursor, Read, Seek};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use zip::result::ZipError;
 use zip::ZipArchive;
 
-pub fn install_paper(args: ServerInstallArgs<'_>) -> anyhow::Result<()> {
-    let paper_cache_dir = args.cache_dir.join("paper");
+const MAX_OLDER_BUILD_ATTEMPTS: usize = 5;
+
+pub fn install_paper(args: ServerInstallArgs<'_>) -> anyhow::Result<InstalledServer> {
+    let (mut paperclip_path, mut paper_build) = download_paperclip(
+        args.client,
+        args.cache_dir,
+        args.version_name,
+        args.command.paper_build,
+        args.command.loader_channel,
+    )?;
+    // paperclip itself runs on java 8, so check what the server it patches needs before running it
+    if !args.command.skip_java_check {
+        if let Some(required_java) = read_bundled_java_version(&paperclip_path)? {
+            verbose!("paper build {paper_build} needs java {required_java}");
+            if args.java_candidate.version.major < required_java {
+                (paperclip_path, paper_build) =
+                    find_older_compatible_build(&args, paper_build, required_java)?;
+            }
+        }
+    }
+
+    let server_jar_path = download_vanilla_server(&args)?;
+
+    let mojang_jar_name = find_mojang_jar_name(&paperclip_path)?
+        .unwrap_or_else(|| format!("mojang_{}.jar", args.version_name));
+
+    fs::create_dir_all(args.instance_path).with_path_context(args.instance_path)?;
+
+    let paperclip_link_path = args.instance_path.join("paperclip.jar");
+    args.link_jar(&paperclip_path, &paperclip_link_path)?;
+
+    let paperclip_cache_dir = args.instance_path.join("cache");
+    fs::create_dir_all(&paperclip_cache_dir).with_path_context(&paperclip_cache_dir)?;
+    let mojang_jar_path = paperclip_cache_dir.join(mojang_jar_name);
+    args.link_jar(&server_jar_path, &mojang_jar_path)?;
+
+    status!("running paperclip");
+    let output = Command::new(&args.java_candidate.path)
+        .arg("-Dpaperclip.patchonly=true")
+        .arg("-jar")
+        .arg("paperclip.jar")
+        .current_dir(args.instance_path)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        bail!("paperclip exited with code {}", output.status)
+    }
+    if let Some(patched_jar_path) = find_patched_jar_path(&paperclip_path)? {
+        let patched_jar_path = args.instance_path.join("versions").join(patched_jar_path);
+        if patched_jar_path.exists() {
+            check_jar_java_version(&patched_jar_path, "the server", paper_build, &args)?;
+        }
+    }
+
+    let launch = args.launch_metadata(Vec::new());
+    let eula_accepted = args.write_launch_files(&launch)?;
+
+    Ok(InstalledServer {
+        launch,
+        eula_accepted,
+        loader_version: Some(paper_build.to_string()),
+        installer_version: None,
+    })
+}
+
+// downloads paperclip into the cache, returning its path and the build
+pub fn download_paperclip(
+    client: &Client,
+    cache_dir: &Path,
+    version_name: &str,
+    requested_build: Option<u32>,
+    loader_channel: LoaderChannel,
+) -> anyhow::Result<(PathBuf, u32)> {
+    let paper_cache_dir = cache_dir.join("paper");
     fs::create_dir_all(&paper_cache_dir).with_path_context(&paper_cache_dir)?;
 
-    let paper_build = match args.command.paper_build {
+    let paper_build = match requested_build {
         Some(paper_build) => paper_build,
         None => {
-            eprintln!("fetching paper builds");
-            let builds: PaperBuilds = ioutil::download_with_etag(
-                args.client,
-                &format!(
-                    "https://api.papermc.io/v2/projects/paper/versions/{}",
-                    urlencoding::encode(args.version_name)
-                ),
-                &paper_cache_dir.join(format!("version-info-{}.json", args.version_name)),
-                JsonDeserializer::new(),
-            )?;
-            builds
+            let builds = fetch_paper_builds(client, &paper_cache_dir, version_name)?;
+            let latest_stable = builds
                 .builds
                 .iter()
-                .copied()
-                .max()
-                .ok_or_else(|| anyhow!("no paper builds for this minecraft version"))?
+                .filter(|build| build.channel == PaperChannel::Default)
+                .map(|build| build.build)
+                .max();
+            let latest = builds.builds.iter().map(|build| build.build).max();
+            match latest_stable {
+                Some(latest_stable) if loader_channel == LoaderChannel::Stable => latest_stable,
+                _ => {
+                    let latest = latest
+                        .ok_or_else(|| anyhow!("no paper builds for this minecraft version"))?;
+                    if loader_channel == LoaderChannel::Stable {
+                        eprintln!("warning: there are no stable paper builds for this minecraft version, using experimental build {latest}");
+                    }
+                    latest
+                }
+            }
         }
     };
 
-    eprintln!("fetching paper build metadata");
-    let build_metadata: PaperBuildMetadata = ioutil::download_with_etag(
-        args.client,
+    verbose!("using paper build {paper_build}");
+    status!("fetching paper build metadata");
+    let build_metadata: PaperBuildMetadata = match ioutil::download_with_etag(
+        client,
         &format!(
-            "https://api.papermc.io/v2/projects/paper/versions/{}/builds/{}",
-            args.version_name, paper_build
+            "{}/v2/projects/paper/versions/{}/builds/{}",
+            mirror::paper_api_url(),
+            version_name,
+            paper_build
         ),
         &paper_cache_dir.join(format!(
             "build-metadata-{}-{}.json",
-            args.version_name, paper_build
+            version_name, paper_build
         )),
         JsonDeserializer::new(),
-    )?;
+    ) {
+        Ok(build_metadata) => build_metadata,
+        // an explicit --paper-build skips the builds endpoint, so this may be the first 404
+        Err(err)
+            if requested_build.is_some() && ioutil::has_status(&err, StatusCode::NOT_FOUND) =>
+        {
+            if let Some(err) = unsupported_version_error(client, &paper_cache_dir, version_name)? {
+                return Err(err);
+            }
+            bail!("Paper has no build {paper_build} for Minecraft {version_name}");
+        }
+        Err(err) => return Err(err),
+    };
+    if requested_build.is_some() && build_metadata.channel != PaperChannel::Default {
+        eprintln!("warning: paper build {paper_build} is experimental and may be unstable");
+    }
 
     let pb = RefCell::new(None);
-    let paperclip_path = paper_cache_dir.join(format!(
-        "paperclip-{}-{}.jar",
-        args.version_name, paper_build
-    ));
+    let paperclip_path =
+        paper_cache_dir.join(format!("paperclip-{}-{}.jar", version_name, paper_build));
     ioutil::download_large_with_hash(
-        args.client,
+        client,
         format!(
-            "https://api.papermc.io/v2/projects/paper/versions/{}/builds/{}/downloads/{}",
-            args.version_name, paper_build, build_metadata.downloads.application.name
+            "{}/v2/projects/paper/versions/{}/builds/{}/downloads/{}",
+            mirror::paper_api_url(),
+            version_name,
+            paper_build,
+            build_metadata.downloads.application.name
         ),
         &paperclip_path,
         HashAlgorithm::Sha256,
         &build_metadata.downloads.application.sha256.inner,
         |download_size| {
             if let Some(download_size) = download_size {
-                *pb.borrow_mut() = Some(make_progress_bar(download_size, "downloading paperclip"));
+                *pb.borrow_mut() = Some(make_progress_bar(
+                    download_size,
+                    &paperclip_path,
+                    "downloading paperclip",
+                ));
             } else {
-                eprintln!("downloading paperclip");
+                status!("downloading paperclip");
             }
         },
         |progress| {
@@ -81,61 +185,308 @@ pub fn install_paper(args: ServerInstallArgs<'_>) -> anyhow::Result<()> {
             }
         },
     )?;
+    ioutil::validate_jar(&paperclip_path)?;
     if let Some(pb) = pb.into_inner() {
-        pb.finish_with_message("downloaded paperclip");
+        finish_progress_bar(&pb, "downloaded paperclip");
     }
 
-    let server_jar_path = download_vanilla_server(&args)?;
+    Ok((paperclip_path, paper_build))
+}
 
-    let mojang_jar_name = find_mojang_jar_name(&paperclip_path)?
-        .unwrap_or_else(|| format!("mojang_{}.jar", args.version_name));
+fn fetch_paper_builds(
+    client: &Client,
+    paper_cache_dir: &Path,
+    version_name: &str,
+) -> anyhow::Result<PaperBuilds> {
+    status!("fetching paper builds");
+    match ioutil::download_with_etag(
+        client,
+        &format!(
+            "{}/v2/projects/paper/versions/{}/builds",
+            mirror::paper_api_url(),
+            urlencoding::encode(version_name)
+        ),
+        &paper_cache_dir.join(format!("builds-{}.json", version_name)),
+        JsonDeserializer::new(),
+    ) {
+        Ok(builds) => Ok(builds),
+        // the build endpoints just 404 for versions paper hasn't built, such as new snapshots
+        Err(err) if ioutil::has_status(&err, StatusCode::NOT_FOUND) => {
+            Err(unsupported_version_error(client, paper_cache_dir, version_name)?.unwrap_or(err))
+        }
+        Err(err) => Err(err),
+    }
+}
 
-    fs::create_dir_all(args.instance_path).with_path_context(args.instance_path)?;
+// None if paper does list the version, in which case the 404 was for something else
+fn unsupported_version_error(
+    client: &Client,
+    paper_cache_dir: &Path,
+    version_name: &str,
+) -> anyhow::Result<Option<anyhow::Error>> {
+    status!("fetching paper versions");
+    // only consulted after a 404, so make sure a stale copy doesn't hide a newly supported version
+    let project: PaperProject = ioutil::revalidate_with_etag(
+        client,
+        &format!("{}/v2/projects/paper", mirror::paper_api_url()),
+        &paper_cache_dir.join("project.json"),
+        JsonDeserializer::new(),
+    )?;
+    if project
+        .versions
+        .iter()
+        .any(|version| version == version_name)
+    {
+        return Ok(None);
+    }
 
-    let paperclip_link_path = args.instance_path.join("paperclip.jar");
-    ioutil::link_or_copy(&paperclip_path, &paperclip_link_path).with_context(|| {
-        format!(
-            "linking {} to {}",
-            paperclip_link_path.display(),
-            paperclip_path.display()
-        )
-    })?;
+    let Some(requested) = parse_release_version(version_name) else {
+        // snapshots and the like, which paper never builds
+        return Ok(Some(match project.versions.last() {
+            Some(newest) => anyhow!(
+                "Paper has no builds for Minecraft {version_name}, the newest version Paper supports is {newest}"
+            ),
+            None => anyhow!("Paper has no builds for Minecraft {version_name}"),
+        }));
+    };
+    let mut releases: Vec<_> = project
+        .versions
+        .iter()
+        .filter_map(|version| Some((parse_release_version(version)?, version)))
+        .collect();
+    releases.sort();
+    let older = releases
+        .iter()
+        .rev()
+        .find(|(parsed, _)| *parsed < requested);
+    let newer = releases.iter().find(|(parsed, _)| *parsed > requested);
 
-    let paperclip_cache_dir = args.instance_path.join("cache");
-    fs::create_dir(&paperclip_cache_dir).with_path_context(&paperclip_cache_dir)?;
-    let mojang_jar_path = paperclip_cache_dir.join(mojang_jar_name);
-    ioutil::link_or_copy(&server_jar_path, &mojang_jar_path).with_context(|| {
-        format!(
-            "linking {} to {}",
-            mojang_jar_path.display(),
-            server_jar_path.display()
-        )
-    })?;
+    Ok(Some(match (older, newer) {
+        (Some((_, older)), None) => anyhow!(
+            "Paper has no builds for Minecraft {version_name} yet, the newest version Paper supports is {older}"
+        ),
+        (None, Some((_, newer))) => anyhow!(
+            "Paper has no builds for Minecraft {version_name}, the oldest version Paper supports is {newer}"
+        ),
+        (Some((_, older)), Some((_, newer))) => anyhow!(
+            "Paper has no builds for Minecraft {version_name}, the nearest versions Paper supports are {older} and {newer}"
+        ),
+        (None, None) => anyhow!("Paper has no builds for Minecraft {version_name}"),
+    }))
+}
 
-    eprintln!("running paperclip");
-    let output = Command::new(&args.java_candidate.path)
-        .arg("-Dpaperclip.patchonly=true")
-        .arg("-jar")
-        .arg("paperclip.jar")
-        .current_dir(args.instance_path)
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .output()?;
-    if !output.status.success() {
-        bail!("paperclip exited with code {}", output.status)
+fn parse_release_version(version: &str) -> Option<Vec<u32>> {
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+pub fn launch_args() -> Vec<String> {
+    vec!["-jar".to_owned(), "paperclip.jar".to_owned()]
+}
+
+fn check_jar_java_version(
+    jar_path: &Path,
+    what: &str,
+    paper_build: u32,
+    args: &ServerInstallArgs<'_>,
+) -> anyhow::Result<()> {
+    if args.command.skip_java_check {
+        return Ok(());
+    }
+    let Some(required_java) = read_required_java_version(jar_path)? else {
+        return Ok(());
+    };
+    verbose!("{what} of paper build {paper_build} needs java {required_java}");
+    let java_candidate = args.java_candidate;
+    if java_candidate.version.major < required_java {
+        bail!(
+            "paper build {paper_build} requires java {required_java} but you selected java {} ({}), {what} would fail with UnsupportedClassVersionError. Install java {required_java} or newer, or pick an older minecraft version",
+            java_candidate.version.major,
+            java_candidate.path.display()
+        );
     }
+    Ok(())
+}
 
-    write_run_server_file(
-        &args,
-        &format!("{} -jar paperclip.jar", args.escaped_java_exe_name()?),
-    )?;
+// offers to try older builds of the same minecraft version, since paper's api doesn't say which java each build needs
+fn find_older_compatible_build(
+    args: &ServerInstallArgs<'_>,
+    paper_build: u32,
+    required_java: u32,
+) -> anyhow::Result<(PathBuf, u32)> {
+    let java_candidate = args.java_candidate;
+    let java_major = java_candidate.version.major;
+    let message = format!(
+        "paper build {paper_build} requires java {required_java} but you selected java {java_major} ({})",
+        java_candidate.path.display()
+    );
+    let advice =
+        format!("Install java {required_java} or newer, or pick an older minecraft version");
+    if args.command.paper_build.is_some() {
+        bail!("{message}, paperclip would fail with UnsupportedClassVersionError. {advice}");
+    }
+    if !confirm(
+        &format!("{message}. Look for an older paper build that java {java_major} can run?"),
+        false,
+        false,
+    )? {
+        bail!("{message}. {advice}");
+    }
+
+    let paper_cache_dir = args.cache_dir.join("paper");
+    let builds = fetch_paper_builds(args.client, &paper_cache_dir, args.version_name)?;
+    let mut older_builds: Vec<_> = builds
+        .builds
+        .iter()
+        .filter(|build| {
+            build.build < paper_build
+                && (args.command.loader_channel == LoaderChannel::Latest
+                    || build.channel == PaperChannel::Default)
+        })
+        .map(|build| build.build)
+        .collect();
+    older_builds.sort_unstable_by(|build1, build2| build2.cmp(build1));
+    // each try downloads a whole paperclip jar
+    for &older_build in older_builds.iter().take(MAX_OLDER_BUILD_ATTEMPTS) {
+        let (paperclip_path, older_build) = download_paperclip(
+            args.client,
+            args.cache_dir,
+            args.version_name,
+            Some(older_build),
+            args.command.loader_channel,
+        )?;
+        match read_bundled_java_version(&paperclip_path)? {
+            Some(required_java) if java_major < required_java => {
+                verbose!("paper build {older_build} needs java {required_java} too");
+            }
+            _ => {
+                status!("using paper build {older_build}, which java {java_major} can run");
+                return Ok((paperclip_path, older_build));
+            }
+        }
+    }
+    if older_builds.is_empty() {
+        bail!(
+            "{message}, and there are no older paper builds for minecraft {}. {advice}",
+            args.version_name
+        );
+    }
+    bail!(
+        "{message}, and none of the {} paper builds before it that were tried run on java {java_major} either. {advice}",
+        older_builds.len().min(MAX_OLDER_BUILD_ATTEMPTS)
+    );
+}
 
-    agree_to_eula(&args)?;
+// the paper api is bundled as a library in newer versions of paperclip, and is built for the same java as the server.
+// None for older versions of paperclip, which only have the patch
+fn read_bundled_java_version(paperclip_jar: &Path) -> anyhow::Result<Option<u32>> {
+    let file = File::open(paperclip_jar).with_path_context(paperclip_jar)?;
+    let mut archive = ZipArchive::new(file).with_path_context(paperclip_jar)?;
+    let mut contents = String::new();
+    match archive.by_name("META-INF/libraries.list") {
+        Ok(mut file) => {
+            file.read_to_string(&mut contents)
+                .with_path_context(paperclip_jar)?;
+        }
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err).with_path_context(paperclip_jar),
+    }
+    // each line is the hash, id and path separated by tabs, like versions.list
+    let Some(api_path) = contents.lines().find_map(|line| {
+        let mut parts = line.splitn(3, '\t');
+        let id = parts.nth(1)?;
+        if id.contains(":paper-api:") {
+            parts.next()
+        } else {
+            None
+        }
+    }) else {
+        return Ok(None);
+    };
 
-    Ok(())
+    let mut api_jar = Vec::new();
+    match archive.by_name(&format!("META-INF/libraries/{}", api_path.trim())) {
+        Ok(mut file) => {
+            file.read_to_end(&mut api_jar)
+                .with_path_context(paperclip_jar)?;
+        }
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err).with_path_context(paperclip_jar),
+    }
+    let mut api_archive = ZipArchive::new(Cursor::new(api_jar)).with_path_context(paperclip_jar)?;
+    // multi-release jars may have classes for newer javas under META-INF/versions
+    let Some(class_path) = api_archive
+        .file_names()
+        .find(|name| name.ends_with(".class") && !name.starts_with("META-INF/"))
+        .map(str::to_owned)
+    else {
+        return Ok(None);
+    };
+    read_class_java_version(&mut api_archive, &class_path, paperclip_jar)
 }
 
-fn find_mojang_jar_name(paperclip_jar: &Path) -> anyhow::Result<Option<String>> {
+// goes by the class file version of the jar's main class, None if it has no main class
+fn read_required_java_version(jar_path: &Path) -> anyhow::Result<Option<u32>> {
+    let file = File::open(jar_path).with_path_context(jar_path)?;
+    let mut archive = ZipArchive::new(file).with_path_context(jar_path)?;
+    let mut manifest = String::new();
+    match archive.by_name("META-INF/MANIFEST.MF") {
+        Ok(mut file) => {
+            file.read_to_string(&mut manifest)
+                .with_path_context(jar_path)?;
+        }
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err).with_path_context(jar_path),
+    }
+    let Some(main_class) = manifest
+        .lines()
+        .find_map(|line| line.strip_prefix("Main-Class:"))
+    else {
+        return Ok(None);
+    };
+    let class_path = format!("{}.class", main_class.trim().replace('.', "/"));
+    read_class_java_version(&mut archive, &class_path, jar_path)
+}
+
+fn read_class_java_version<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    class_path: &str,
+    jar_path: &Path,
+) -> anyhow::Result<Option<u32>> {
+    let mut header = [0; 8];
+    match archive.by_name(class_path) {
+        Ok(mut file) => file.read_exact(&mut header).with_path_context(jar_path)?,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err).with_path_context(jar_path),
+    }
+    if header[..4] != [0xca, 0xfe, 0xba, 0xbe] {
+        bail!("{class_path} in {} is not a class file", jar_path.display());
+    }
+    // class file version 52 is java 8, and each java release since has added one
+    let class_version = u16::from_be_bytes([header[6], header[7]]) as u32;
+    Ok(Some(class_version.saturating_sub(44)))
+}
+
+// where paperclip puts the patched server, relative to the versions folder
+fn find_patched_jar_path(paperclip_jar: &Path) -> anyhow::Result<Option<String>> {
+    let file = File::open(paperclip_jar).with_path_context(paperclip_jar)?;
+    let mut archive = ZipArchive::new(file).with_path_context(paperclip_jar)?;
+    let mut contents = String::new();
+    match archive.by_name("META-INF/versions.list") {
+        Ok(mut file) => {
+            file.read_to_string(&mut contents)
+                .with_path_context(paperclip_jar)?;
+        }
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err).with_path_context(paperclip_jar),
+    }
+    // each line is the hash, id and path separated by tabs
+    Ok(contents
+        .lines()
+        .find_map(|line| line.splitn(3, '\t').nth(2))
+        .map(|path| path.trim().to_owned()))
+}
+
+pub fn find_mojang_jar_name(paperclip_jar: &Path) -> anyhow::Result<Option<String>> {
     let file = File::open(paperclip_jar).with_path_context(paperclip_jar)?;
     let mut archive = ZipArchive::new(file).with_path_context(paperclip_jar)?;
     let result = match archive.by_name("META-INF/download-context") {
@@ -154,16 +505,38 @@ fn find_mojang_jar_name(paperclip_jar: &Path) -> anyhow::Result<Option<String>>
     result
 }
 
+// versions are listed oldest first
+#[derive(Debug, Deserialize)]
+struct PaperProject {
+    versions: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct PaperBuilds {
-    builds: Vec<u32>,
+    builds: Vec<PaperBuild>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PaperBuild {
+    build: u32,
+    channel: PaperChannel,
 }
 
 #[derive(Debug, Deserialize)]
 struct PaperBuildMetadata {
+    channel: PaperChannel,
     downloads: PaperDownloads,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PaperChannel {
+    Default,
+    Experimental,
+    #[serde(other)]
+    Unknown,
+}
+
 #[derive(Debug, Deserialize)]
 struct PaperDownloads {
     application: PaperDownload,
@@ -174,3 +547,62 @@ struct PaperDownload {
     name: String,
     sha256: Sha2String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempdir::TempDir;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    fn make_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn bundled_paper_api_gives_java_version() {
+        // class file version 65 is java 21
+        let class = [0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 65];
+        let api_jar = make_zip(&[
+            ("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0\r\n"),
+            ("org/bukkit/Bukkit.class", &class),
+        ]);
+        let api_path =
+            "io/papermc/paper/paper-api/1.21.4-R0.1-SNAPSHOT/paper-api-1.21.4-R0.1-SNAPSHOT.jar";
+        let libraries_list = format!(
+            "0000\tcom.google.guava:guava:33.3.1-jre\tcom/google/guava/guava/33.3.1-jre/guava-33.3.1-jre.jar\n0000\tio.papermc.paper:paper-api:1.21.4-R0.1-SNAPSHOT\t{api_path}\n"
+        );
+        let paperclip = make_zip(&[
+            ("META-INF/libraries.list", libraries_list.as_bytes()),
+            (&format!("META-INF/libraries/{api_path}"), &api_jar),
+        ]);
+
+        let dir = TempDir::new("paperclip").unwrap();
+        let paperclip_path = dir.path().join("paperclip.jar");
+        fs::write(&paperclip_path, paperclip).unwrap();
+        assert_eq!(
+            read_bundled_java_version(&paperclip_path).unwrap(),
+            Some(21)
+        );
+    }
+
+    #[test]
+    fn old_paperclip_has_no_bundled_java_version() {
+        let dir = TempDir::new("paperclip").unwrap();
+        let paperclip_path = dir.path().join("paperclip.jar");
+        fs::write(
+            &paperclip_path,
+            make_zip(&[("META-INF/MANIFEST.MF", b"Manifest-Version: 1.0\r\n")]),
+        )
+        .unwrap();
+        assert_eq!(read_bundled_java_version(&paperclip_path).unwrap(), None);
+    }
+}
@@ -0,0 +1,76 @@
+use crate::commands::new::{InstalledServer, ServerInstallArgs};
+use crate::ioutil::JarDeserializer;
+use crate::output::status;
+use crate::{ioutil, ContextExt};
+use anyhow::{bail, Context};
+use std::fs;
+use std::process::{Command, Stdio};
+
+const BUILD_TOOLS_URL: &str =
+    "https://hub.spigotmc.org/jenkins/job/BuildTools/lastSuccessfulBuild/artifact/target/BuildTools.jar";
+
+pub fn install_spigot(args: ServerInstallArgs<'_>) -> anyhow::Result<InstalledServer> {
+    let spigot_cache_dir = args.cache_dir.join("spigot");
+    fs::create_dir_all(&spigot_cache_dir).with_path_context(&spigot_cache_dir)?;
+
+    let spigot_jar_name = format!("spigot-{}.jar", args.version_name);
+    let spigot_jar_path = spigot_cache_dir.join(&spigot_jar_name);
+    if spigot_jar_path.exists() {
+        status!("using previously built {spigot_jar_name}");
+    } else {
+        status!("downloading buildtools");
+        let build_tools_path = spigot_cache_dir.join("BuildTools.jar");
+        ioutil::download_with_etag(
+            args.client,
+            BUILD_TOOLS_URL,
+            &build_tools_path,
+            JarDeserializer,
+        )?;
+
+        let build_dir = spigot_cache_dir.join("build");
+        fs::create_dir_all(&build_dir).with_path_context(&build_dir)?;
+
+        status!("running buildtools, this may take a while");
+        let output = Command::new(&args.java_candidate.path)
+            .arg("-jar")
+            .arg(&build_tools_path)
+            .arg("--rev")
+            .arg(args.version_name)
+            .arg("--output-dir")
+            .arg(&spigot_cache_dir)
+            .current_dir(&build_dir)
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .output()
+            .context("running buildtools")?;
+        if !output.status.success() {
+            bail!("buildtools exited with code {}", output.status)
+        }
+        if !spigot_jar_path.exists() {
+            bail!("buildtools did not produce {spigot_jar_name}");
+        }
+    }
+
+    fs::create_dir_all(args.instance_path).with_path_context(args.instance_path)?;
+
+    let spigot_link_path = args.instance_path.join("spigot.jar");
+    args.link_jar(&spigot_jar_path, &spigot_link_path)?;
+
+    let launch = args.launch_metadata(Vec::new());
+    let eula_accepted = args.write_launch_files(&launch)?;
+
+    Ok(InstalledServer {
+        launch,
+        eula_accepted,
+        loader_version: None,
+        installer_version: None,
+    })
+}
+
+pub fn launch_args() -> Vec<String> {
+    vec![
+        "-jar".to_owned(),
+        "spigot.jar".to_owned(),
+        "nogui".to_owned(),
+    ]
+}
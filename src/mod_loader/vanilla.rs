@@ -1,7 +1,14 @@
-use crate::commands::new::{write_run_server_file, ServerInstallArgs};
-use crate::{ioutil, make_progress_bar, ContextExt, LINE_ENDING};
-use anyhow::{bail, Context};
-use std::path::PathBuf;
+use crate::cli::{accept_all_prompts, NewCommand};
+use crate::commands::new::{InstalledServer, ServerInstallArgs};
+use crate::mod_loader::ModLoader;
+use crate::mojang::Version;
+use crate::output::verbose;
+use crate::{finish_progress_bar, ioutil, make_progress_bar, ContextExt, LINE_ENDING};
+use anyhow::bail;
+use reqwest::blocking::Client;
+use std::cell::RefCell;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use std::{fs, io};
 use time::macros::datetime;
 use time::OffsetDateTime;
@@ -10,75 +17,239 @@ const TIME_13W39A: OffsetDateTime = datetime!(2013-09-26 15:11:19 UTC);
 const TIME_17W15A: OffsetDateTime = datetime!(2017-04-12 09:30:50 UTC);
 const TIME_1_17_PRE1: OffsetDateTime = datetime!(2021-05-27 09:39:21 UTC);
 const TIME_1_18_1_RC3: OffsetDateTime = datetime!(2021-12-10 03:36:38 UTC);
+// mojang's old download bucket, which has server jars for some versions the manifest has no server download for
+const LEGACY_SERVER_URL: &str =
+    "https://s3.amazonaws.com/Minecraft.Download/versions/{version}/minecraft_server.{version}.jar";
 
-pub fn install_vanilla(args: ServerInstallArgs<'_>) -> anyhow::Result<()> {
+pub fn install_vanilla(args: ServerInstallArgs<'_>) -> anyhow::Result<InstalledServer> {
     let server_jar_path = download_vanilla_server(&args)?;
 
     fs::create_dir_all(args.instance_path).with_path_context(args.instance_path)?;
 
-    let link_path = args.instance_path.join("server.jar");
-    ioutil::link_or_copy(&server_jar_path, &link_path).with_context(|| {
-        format!(
-            "linking {} to {}",
-            link_path.display(),
-            server_jar_path.display()
-        )
-    })?;
-
-    let mut start_server_command = format!("{} ", args.escaped_java_exe_name()?);
-
-    apply_vanilla_log4j_fix(&args, &mut start_server_command)?;
+    let link_path = args
+        .instance_path
+        .join(args.server_jar_name.unwrap_or("server.jar"));
+    args.link_jar(&server_jar_path, &link_path)?;
 
-    start_server_command.push_str("-jar server.jar nogui");
-    start_server_command.push_str(LINE_ENDING);
+    let mut jvm_args = Vec::new();
+    apply_vanilla_log4j_fix(&args, &mut jvm_args)?;
 
-    write_run_server_file(&args, &start_server_command)?;
+    let launch = args.launch_metadata(jvm_args);
+    let eula_accepted = args.write_launch_files(&launch)?;
 
-    agree_to_eula(&args)?;
+    Ok(InstalledServer {
+        launch,
+        eula_accepted,
+        loader_version: None,
+        installer_version: None,
+    })
+}
 
-    Ok(())
+pub fn launch_args(server_jar_name: Option<&str>) -> Vec<String> {
+    vec![
+        "-jar".to_owned(),
+        server_jar_name.unwrap_or("server.jar").to_owned(),
+        "nogui".to_owned(),
+    ]
 }
 
 pub fn download_vanilla_server(args: &ServerInstallArgs<'_>) -> anyhow::Result<PathBuf> {
-    let Some(server_download) = &args.full_version.downloads.server else {
-        bail!(
-            "version {} does not have a server download",
-            args.version_name
+    let legacy_archive_url = args
+        .command
+        .legacy_archive_url
+        .as_deref()
+        .filter(|_| args.command.allow_legacy_archives);
+    download_server_jar(
+        args.client,
+        args.cache_dir,
+        args.version_name,
+        args.full_version,
+        legacy_archive_url,
+    )
+}
+
+// downloads the server jar into the cache, returning its path
+pub fn download_server_jar(
+    client: &Client,
+    cache_dir: &Path,
+    version_name: &str,
+    full_version: &Version,
+    legacy_archive_url: Option<&str>,
+) -> anyhow::Result<PathBuf> {
+    let server_download_path = cache_dir.join("jars");
+    fs::create_dir_all(&server_download_path)?;
+    let server_jar_path = server_download_path.join(format!("{version_name}.jar"));
+
+    let Some(server_download) = &full_version.downloads.server else {
+        return download_legacy_server_jar(
+            client,
+            version_name,
+            &server_download_path,
+            legacy_archive_url,
         );
     };
-    let server_download_path = args.cache_dir.join("jars");
-    fs::create_dir_all(&server_download_path)?;
-    let server_jar_path = server_download_path.join(format!("{}.jar", args.version_name));
 
-    let pb = make_progress_bar(server_download.size, "downloading server jar");
-    server_download.download(args.client, &server_jar_path, |progress| {
+    let pb = make_progress_bar(
+        server_download.size,
+        &server_jar_path,
+        "downloading server jar",
+    );
+    server_download.download(client, &server_jar_path, |progress| {
         pb.set_position(progress)
     })?;
-    pb.finish_with_message("downloaded server jar");
+    ioutil::validate_jar(&server_jar_path)?;
+    finish_progress_bar(&pb, "downloaded server jar");
 
     Ok(server_jar_path)
 }
 
-pub fn agree_to_eula(args: &ServerInstallArgs<'_>) -> anyhow::Result<()> {
-    let mut eula = args.command.eula;
-    if !eula {
-        eprintln!("Do you agree to the Minecraft EULA (y/N)? You can read the EULA at https://aka.ms/MinecraftEULA");
-        let mut input = String::new();
-        io::stdin().read_line(&mut input)?;
-        eula = input.starts_with('y') || input.starts_with('Y');
+// there's no hash to check these against, so a jar already in the cache is trusted.
+// Unofficial jars are cached under their own name so they're never mistaken for one from mojang
+fn download_legacy_server_jar(
+    client: &Client,
+    version_name: &str,
+    server_download_path: &Path,
+    legacy_archive_url: Option<&str>,
+) -> anyhow::Result<PathBuf> {
+    let official_path = server_download_path.join(format!("{version_name}.jar"));
+    let unofficial_path = server_download_path.join(format!("{version_name}-unofficial.jar"));
+    if ioutil::validate_jar(&official_path).is_ok() {
+        verbose!("using cached {}", official_path.display());
+        return Ok(official_path);
+    }
+
+    let mut sources = vec![(LEGACY_SERVER_URL, false)];
+    if let Some(legacy_archive_url) = legacy_archive_url {
+        sources.push((legacy_archive_url, true));
+    }
+    for (template, unofficial) in sources {
+        let path = if unofficial {
+            &unofficial_path
+        } else {
+            &official_path
+        };
+        if unofficial && ioutil::validate_jar(path).is_ok() {
+            eprintln!(
+                "warning: using an unofficial server jar cached from the legacy archive, it can't be checked against a hash from Mojang"
+            );
+            return Ok(path.clone());
+        }
+        let url = template.replace("{version}", &urlencoding::encode(version_name));
+        verbose!("trying {url}");
+        if unofficial {
+            eprintln!("warning: downloading an unofficial server jar from {url}, it can't be checked against a hash from Mojang");
+        }
+        match download_unverified_jar(client, &url, path) {
+            Ok(()) => return Ok(path.clone()),
+            Err(err) => {
+                let _ = fs::remove_file(path);
+                verbose!("could not download {url}: {err:#}");
+            }
+        }
+    }
+
+    if legacy_archive_url.is_some() {
+        bail!("version {version_name} does not have a server download, and it could not be found in Mojang's legacy downloads or the legacy archive");
+    }
+    bail!("version {version_name} does not have a server download, and it could not be found in Mojang's legacy downloads. Pass --allow-legacy-archives and --legacy-archive-url to look in a community archive");
+}
+
+fn download_unverified_jar(client: &Client, url: &str, path: &Path) -> anyhow::Result<()> {
+    let pb = RefCell::new(None);
+    ioutil::download_large(
+        client,
+        url,
+        path,
+        |download_size| {
+            *pb.borrow_mut() = Some(make_progress_bar(
+                download_size.unwrap_or_default(),
+                path,
+                "downloading server jar",
+            ));
+        },
+        |progress| {
+            if let Some(pb) = &*pb.borrow() {
+                pb.set_position(progress);
+            }
+        },
+    )?;
+    ioutil::validate_jar(path)?;
+    if let Some(pb) = pb.into_inner() {
+        finish_progress_bar(&pb, "downloaded server jar");
+    }
+    Ok(())
+}
+
+// returns whether eula=true was written
+pub fn agree_to_eula(args: &ServerInstallArgs<'_>) -> anyhow::Result<bool> {
+    // a repaired instance keeps its answer, so there's no need to ask again
+    let eula_path = args.instance_path.join("eula.txt");
+    if args.command.repair && eula_path.exists() {
+        return Ok(false);
     }
 
-    if eula {
-        let eula_path = args.instance_path.join("eula.txt");
-        fs::write(&eula_path, format!("eula=true{}", LINE_ENDING)).with_path_context(&eula_path)?;
+    let Some(eula) = ask_eula(args.command)? else {
+        return Ok(false);
+    };
+    fs::write(&eula_path, format!("eula={eula}{}", LINE_ENDING)).with_path_context(&eula_path)?;
+
+    Ok(eula)
+}
+
+// what is being agreed to, and where to read it
+fn eula_terms(loader: ModLoader) -> (&'static str, &'static str) {
+    match loader {
+        // the download page makes you agree to the privacy policy as well
+        ModLoader::Bedrock => (
+            "the Minecraft EULA and Privacy Policy",
+            "You can read them at https://aka.ms/MinecraftEULA and https://go.microsoft.com/fwlink/?LinkId=521839",
+        ),
+        ModLoader::Vanilla | ModLoader::Fabric | ModLoader::Paper | ModLoader::Spigot => (
+            "the Minecraft EULA",
+            "You can read the EULA at https://aka.ms/MinecraftEULA",
+        ),
     }
+}
 
+// fails if the eula would have to be asked about but can't be, so new can check before downloading anything
+pub fn check_eula_can_be_asked(command: &NewCommand) -> anyhow::Result<()> {
+    if command.eula.is_some() || accept_all_prompts() {
+        return Ok(());
+    }
+    let (terms, where_to_read) = eula_terms(command.loader);
+    if command.no_eula_prompt {
+        bail!("--no-eula-prompt was passed, pass --eula to agree to {terms} or --eula=false to decline. {where_to_read}");
+    }
+    if !io::stdin().is_terminal() {
+        bail!("cannot ask to agree to {terms} because stdin is not interactive, pass --eula to agree to it or --eula=false to decline. {where_to_read}");
+    }
     Ok(())
 }
 
+// returns None if the user declined when prompted, in which case the server will ask again when it's first run
+pub fn ask_eula(command: &NewCommand) -> anyhow::Result<Option<bool>> {
+    if let Some(eula) = command.eula {
+        return Ok(Some(eula));
+    }
+
+    check_eula_can_be_asked(command)?;
+    let (terms, where_to_read) = eula_terms(command.loader);
+    if accept_all_prompts() {
+        eprintln!("Do you agree to {terms} (y/N)? {where_to_read}: y (--accept-all-prompts)");
+        return Ok(Some(true));
+    }
+    eprintln!("Do you agree to {terms} (y/N)? {where_to_read}");
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    if !(input.starts_with('y') || input.starts_with('Y')) {
+        return Ok(None);
+    }
+    Ok(Some(true))
+}
+
 fn apply_vanilla_log4j_fix(
     args: &ServerInstallArgs,
-    start_server_command: &mut String,
+    jvm_args: &mut Vec<String>,
 ) -> anyhow::Result<()> {
     if (TIME_13W39A..TIME_1_18_1_RC3).contains(&args.manifest_version.release_time) {
         if args.manifest_version.release_time < TIME_17W15A {
@@ -88,7 +259,7 @@ fn apply_vanilla_log4j_fix(
                 include_str!("../../res/log4j2_17-111.xml"),
             )
             .with_path_context(&log4j_config_path)?;
-            start_server_command.push_str("-Dlog4j.configurationFile=log4j2_17-111.xml ");
+            jvm_args.push("-Dlog4j.configurationFile=log4j2_17-111.xml".to_owned());
         } else if args.manifest_version.release_time < TIME_1_17_PRE1 {
             let log4j_config_path = args.instance_path.join("log4j2_112-116.xml");
             fs::write(
@@ -96,9 +267,9 @@ fn apply_vanilla_log4j_fix(
                 include_str!("../../res/log4j2_112-116.xml"),
             )
             .with_path_context(&log4j_config_path)?;
-            start_server_command.push_str("-Dlog4j.configurationFile=log4j2_112-116.xml ");
+            jvm_args.push("-Dlog4j.configurationFile=log4j2_112-116.xml".to_owned());
         } else {
-            start_server_command.push_str("-Dlog4j2.formatMsgNoLookups=true ");
+            jvm_args.push("-Dlog4j2.formatMsgNoLookups=true".to_owned());
         }
     }
 
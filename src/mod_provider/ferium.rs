@@ -0,0 +1,117 @@
+use crate::ContextExt;
+use anyhow::bail;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+pub struct FeriumMod {
+    pub name: String,
+    pub source: FeriumSource,
+}
+
+pub enum FeriumSource {
+    Modrinth {
+        project_id: String,
+        // set when the mod is pinned to a version in ferium
+        version_id: Option<String>,
+    },
+    GitHub {
+        // owner/repo
+        repo: String,
+    },
+    // a description of where the mod comes from, for sources we can't install from
+    Unsupported(String),
+}
+
+// reads either a single profile or a whole ferium config, taking its active profile
+pub fn read_ferium_profile(path: &Path) -> anyhow::Result<Vec<FeriumMod>> {
+    let contents = fs::read_to_string(path).with_path_context(path)?;
+    let profile = match serde_json::from_str(&contents).with_path_context(path)? {
+        FeriumFile::Config {
+            active_profile,
+            mut profiles,
+        } => {
+            if active_profile >= profiles.len() {
+                bail!(
+                    "{} has no profile {active_profile} to import",
+                    path.display()
+                );
+            }
+            profiles.swap_remove(active_profile)
+        }
+        FeriumFile::Profile(profile) => profile,
+    };
+
+    Ok(profile
+        .mods
+        .into_iter()
+        .map(|ferium_mod| FeriumMod {
+            source: FeriumSource::from_identifier(ferium_mod.identifier),
+            name: ferium_mod.name,
+        })
+        .collect())
+}
+
+impl FeriumSource {
+    // unknown identifiers are kept as json rather than failing the whole profile
+    fn from_identifier(identifier: serde_json::Value) -> FeriumSource {
+        match serde_json::from_value(identifier.clone()) {
+            Ok(FeriumIdentifier::ModrinthProject(project_id)) => FeriumSource::Modrinth {
+                project_id,
+                version_id: None,
+            },
+            Ok(FeriumIdentifier::PinnedModrinthProject(project_id, version_id)) => {
+                FeriumSource::Modrinth {
+                    project_id,
+                    version_id: Some(version_id),
+                }
+            }
+            Ok(
+                FeriumIdentifier::CurseForgeProject(project_id)
+                | FeriumIdentifier::PinnedCurseForgeProject(project_id, _),
+            ) => FeriumSource::Unsupported(format!("CurseForge project {project_id}")),
+            // pinned github mods are pinned to an asset id, which doesn't say which release it's from
+            Ok(
+                FeriumIdentifier::GitHubRepository((owner, repo))
+                | FeriumIdentifier::PinnedGitHubRepository((owner, repo), _),
+            ) => FeriumSource::GitHub {
+                repo: format!("{owner}/{repo}"),
+            },
+            Err(_) => FeriumSource::Unsupported(format!("unknown source {identifier}")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FeriumFile {
+    Config {
+        #[serde(default)]
+        active_profile: usize,
+        profiles: Vec<FeriumProfile>,
+    },
+    Profile(FeriumProfile),
+}
+
+#[derive(Debug, Deserialize)]
+struct FeriumProfile {
+    #[serde(default)]
+    mods: Vec<FeriumProfileMod>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeriumProfileMod {
+    name: String,
+    identifier: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+enum FeriumIdentifier {
+    ModrinthProject(String),
+    PinnedModrinthProject(String, String),
+    CurseForgeProject(u64),
+    PinnedCurseForgeProject(u64, IgnoredAny),
+    GitHubRepository((String, String)),
+    PinnedGitHubRepository((String, String), IgnoredAny),
+}
@@ -0,0 +1,184 @@
+use crate::cli::select_from_list;
+use crate::commands::add::AddModArgs;
+use crate::instance::ModKind;
+use crate::mod_provider::{ModProvider, PendingMod};
+use crate::output::verbose;
+use anyhow::{bail, Context};
+use indicatif::HumanBytes;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use url::Url;
+
+const GITHUB_API_URL: &str = "https://api.github.com";
+// unauthenticated requests are limited to 60 an hour
+const GITHUB_TOKEN_VAR: &str = "GITHUB_TOKEN";
+
+// github doesn't publish asset hashes, so the download is hashed locally instead
+pub fn resolve_mod(args: AddModArgs<'_>) -> anyhow::Result<Vec<PendingMod>> {
+    let (repo, tag) = parse_repo(args.name)?;
+    let tag = tag.or(args.command.version.as_deref());
+
+    let release = get_release(args.client, &repo, tag)?;
+    let assets: Vec<_> = release
+        .assets
+        .iter()
+        .filter(|asset| asset.name.ends_with(".jar"))
+        .collect();
+    let Some(asset) = select_from_list(
+        assets,
+        &format!(
+            "which file of {repo} {} should be installed?",
+            release.tag_name
+        ),
+        args.command.yes,
+    )?
+    else {
+        bail!("release {} of {repo} has no jar files", release.tag_name);
+    };
+    // the name comes from the api, make sure it can't escape the mods folder
+    if Path::new(&asset.name).file_name() != Some(asset.name.as_ref()) {
+        bail!("GitHub asset has invalid name {}", asset.name);
+    }
+
+    verbose!(
+        "using {repo} {} ({})",
+        release.tag_name,
+        asset.browser_download_url
+    );
+
+    let loader = args.instance_metadata.loader;
+    let Some(target_folder) = ModKind::Mod.folder(loader) else {
+        bail!("cannot install mods on loader '{loader}'");
+    };
+    let target_folder = args.instance_path.join(target_folder);
+
+    let existing_mod = args
+        .instance_metadata
+        .mods
+        .iter()
+        .find(|m| m.provider == ModProvider::GitHub && m.id == repo);
+    if let Some(existing_mod) = existing_mod {
+        if existing_mod.file_name == asset.name
+            && existing_mod.version.as_deref() == Some(&release.tag_name)
+        {
+            if !args.command.reinstall {
+                bail!("mod is already up-to-date");
+            }
+            verbose!("mod is already up-to-date, reinstalling it");
+        }
+    }
+    for m in &args.instance_metadata.mods {
+        if m.id != repo && m.kind == ModKind::Mod && m.file_name == asset.name {
+            bail!(
+                "mod conflicts with existing mod {} ({}), which also has the filename '{}'",
+                m.id,
+                m.name,
+                m.file_name
+            );
+        }
+    }
+
+    let replaces = existing_mod
+        .filter(|existing_mod| existing_mod.file_name != asset.name)
+        .map(|existing_mod| target_folder.join(&existing_mod.file_name));
+    let name = repo
+        .split_once('/')
+        .map_or(repo.as_str(), |(_, name)| name)
+        .to_owned();
+    Ok(vec![PendingMod {
+        label: format!("{repo} {}", release.tag_name),
+        name,
+        id: repo,
+        file_name: asset.name.clone(),
+        url: asset.browser_download_url.clone(),
+        size: asset.size,
+        hash: None,
+        version: Some(release.tag_name.clone()),
+        provider: ModProvider::GitHub,
+        kind: ModKind::Mod,
+        folder: None,
+        target_folder,
+        replaces,
+    }])
+}
+
+// accepts owner/repo, owner/repo@tag or a link to the repo
+fn parse_repo(name: &str) -> anyhow::Result<(String, Option<&str>)> {
+    let (repo, tag) = match name.rsplit_once('@') {
+        Some((repo, tag)) if !tag.is_empty() => (repo, Some(tag)),
+        _ => (name, None),
+    };
+    let repo = repo
+        .strip_prefix("https://github.com/")
+        .unwrap_or(repo)
+        .trim_end_matches('/');
+    match repo.split_once('/') {
+        Some((owner, repo_name))
+            if !owner.is_empty() && !repo_name.is_empty() && !repo_name.contains('/') =>
+        {
+            Ok((repo.to_owned(), tag))
+        }
+        _ => bail!("GitHub mods must be given as owner/repo or owner/repo@tag, got {name}"),
+    }
+}
+
+fn get_release(client: &Client, repo: &str, tag: Option<&str>) -> anyhow::Result<Release> {
+    let url = match tag {
+        Some(tag) => format!(
+            "{GITHUB_API_URL}/repos/{repo}/releases/tags/{}",
+            urlencoding::encode(tag)
+        ),
+        None => format!("{GITHUB_API_URL}/repos/{repo}/releases/latest"),
+    };
+    let mut request = client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json");
+    if let Ok(token) = env::var(GITHUB_TOKEN_VAR) {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send().with_context(|| url.clone())?;
+    match response.status() {
+        StatusCode::NOT_FOUND => match tag {
+            Some(tag) => bail!("{repo} has no release {tag}"),
+            None => bail!("{repo} does not exist or has no releases"),
+        },
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+            if env::var_os(GITHUB_TOKEN_VAR).is_none() =>
+        {
+            bail!(
+                "request to {} returned status code {}, GitHub may be rate limiting you, set {GITHUB_TOKEN_VAR} to a GitHub token to raise the limit",
+                url,
+                response.status()
+            );
+        }
+        status if !status.is_success() => {
+            bail!("request to {} returned status code {}", url, status);
+        }
+        _ => {}
+    }
+
+    response.json().with_context(|| url.clone())
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    size: u64,
+    browser_download_url: Url,
+}
+
+impl Display for ReleaseAsset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, HumanBytes(self.size))
+    }
+}
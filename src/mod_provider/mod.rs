@@ -1,22 +1,156 @@
-mod modrinth;
+pub mod ferium;
+pub mod github;
+pub mod modpack;
+pub mod modrinth;
 
 use crate::commands::add::AddModArgs;
-use crate::instance::ModMetadata;
+use crate::hashing::{HashAlgorithm, HashWithAlgorithm};
+use crate::instance::{ModKind, ModMetadata};
+use crate::ioutil::{download_large, download_large_with_hash, validate_jar};
+use crate::{add_progress_bar, finish_progress_bar, make_progress_bar, ContextExt};
+use anyhow::bail;
 use clap::ValueEnum;
+use indicatif::MultiProgress;
+use reqwest::blocking::Client;
 use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::PathBuf;
+use std::{fs, io};
+use url::Url;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ModProvider {
+    #[value(name = "github")]
+    #[serde(rename = "github")]
+    GitHub,
     Hangar,
+    // added from a file with add --file, there's nowhere to update it from
+    #[value(skip)]
+    Local,
     Modrinth,
 }
 
 impl ModProvider {
-    pub fn add_mod(&self, args: AddModArgs<'_>) -> anyhow::Result<ModMetadata> {
+    pub fn add_mod(&self, args: AddModArgs<'_>) -> anyhow::Result<Vec<ModMetadata>> {
+        let client = args.client;
+        self.resolve_mod(args)?
+            .into_iter()
+            .map(|pending_mod| pending_mod.download(client, None))
+            .collect()
+    }
+
+    pub fn resolve_mod(&self, args: AddModArgs<'_>) -> anyhow::Result<Vec<PendingMod>> {
         match self {
+            Self::GitHub => github::resolve_mod(args),
             Self::Hangar => todo!(),
-            Self::Modrinth => modrinth::add_mod(args),
+            Self::Local => bail!("local mods can only be added with --file"),
+            Self::Modrinth => modrinth::resolve_mod(args),
         }
     }
 }
+
+// a mod that has been picked but not downloaded yet
+#[derive(Debug)]
+pub struct PendingMod {
+    pub id: String,
+    pub name: String,
+    pub file_name: String,
+    pub url: Url,
+    pub size: u64,
+    // None if the provider doesn't publish a hash, it's computed after downloading instead
+    pub hash: Option<HashWithAlgorithm>,
+    // the version number or release tag, for checking for newer versions later
+    pub version: Option<String>,
+    pub provider: ModProvider,
+    pub kind: ModKind,
+    pub target_folder: PathBuf,
+    // target_folder relative to the instance, if it needs recording in the metadata
+    pub folder: Option<String>,
+    // the file of the version being updated from, removed once the new one is downloaded
+    pub replaces: Option<PathBuf>,
+    // what the progress bar shows, e.g. the slug and version
+    pub label: String,
+}
+
+impl PendingMod {
+    pub fn download(
+        self,
+        client: &Client,
+        multi_progress: Option<&MultiProgress>,
+    ) -> anyhow::Result<ModMetadata> {
+        fs::create_dir_all(&self.target_folder).with_path_context(&self.target_folder)?;
+        let mod_path = self.target_folder.join(&self.file_name);
+
+        let message = format!("downloading {}", self.label);
+        let pb = match multi_progress {
+            Some(multi_progress) => add_progress_bar(multi_progress, self.size, &mod_path, message),
+            None => make_progress_bar(self.size, &mod_path, message),
+        };
+        let hash = match self.hash {
+            Some(hash) => {
+                download_large_with_hash(
+                    client,
+                    self.url,
+                    &mod_path,
+                    hash.algorithm,
+                    &hash.hash,
+                    |_| {},
+                    |progress| pb.set_position(progress),
+                )?;
+                hash
+            }
+            None => {
+                download_large(
+                    client,
+                    self.url,
+                    &mod_path,
+                    |_| {},
+                    |progress| pb.set_position(progress),
+                )?;
+                // sha256 is what people are used to comparing against for github releases
+                let algorithm = if self.provider == ModProvider::GitHub {
+                    HashAlgorithm::Sha256
+                } else {
+                    HashAlgorithm::Sha512
+                };
+                let mut digest = algorithm.create_hasher();
+                io::copy(
+                    &mut File::open(&mod_path).with_path_context(&mod_path)?,
+                    &mut digest,
+                )
+                .with_path_context(&mod_path)?;
+                HashWithAlgorithm {
+                    algorithm,
+                    hash: digest.finalize(),
+                }
+            }
+        };
+        finish_progress_bar(&pb, format!("downloaded {}", self.label));
+
+        if let Err(err) = validate_jar(&mod_path) {
+            // don't leave a broken file in the mods folder for the server to trip over
+            let _ = fs::remove_file(&mod_path);
+            return Err(err);
+        }
+
+        if let Some(old_mod_file) = &self.replaces {
+            if let Err(err) = fs::remove_file(old_mod_file) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    return Err(err).with_path_context(old_mod_file);
+                }
+            }
+        }
+
+        Ok(ModMetadata {
+            id: self.id,
+            name: self.name,
+            file_name: self.file_name,
+            hash,
+            version: self.version,
+            provider: self.provider,
+            kind: self.kind,
+            folder: self.folder,
+        })
+    }
+}
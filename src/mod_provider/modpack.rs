@@ -0,0 +1,406 @@
+use crate::hashing::{HashAlgorithm, Sha1String};
+use crate::ioutil::{
+    archive_entry_path, download_large, download_large_with_hash, parallel_map, validate_jar,
+};
+use crate::mod_loader::ModLoader;
+use crate::output::{self, status, verbose};
+use crate::{add_progress_bar, finish_progress_bar, ContextExt};
+use anyhow::{bail, Context};
+use indicatif::MultiProgress;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+use zip::ZipArchive;
+
+const CURSEFORGE_API_URL: &str = "https://api.curseforge.com/v1";
+const CURSEFORGE_API_KEY_VAR: &str = "CURSEFORGE_API_KEY";
+// the algo id curseforge uses for sha1 hashes
+const CURSEFORGE_SHA1_ALGO: u32 = 1;
+
+pub struct CurseForgeModpack {
+    path: PathBuf,
+    manifest: CurseForgeManifest,
+    api_key: String,
+}
+
+impl CurseForgeModpack {
+    pub fn open(path: &Path, api_key: Option<&str>) -> anyhow::Result<CurseForgeModpack> {
+        let file = File::open(path).with_path_context(path)?;
+        let mut archive = ZipArchive::new(file).with_path_context(path)?;
+        let manifest: CurseForgeManifest = match archive.by_name("manifest.json") {
+            Ok(manifest) => serde_json::from_reader(manifest).with_path_context(path)?,
+            Err(zip::result::ZipError::FileNotFound) => {
+                bail!(
+                    "{} is not a CurseForge modpack, it has no manifest.json",
+                    path.display()
+                );
+            }
+            Err(err) => return Err(err).with_path_context(path),
+        };
+        if manifest.manifest_type != "minecraftModpack" {
+            bail!(
+                "{} is not a CurseForge modpack, unknown manifest type {}",
+                path.display(),
+                manifest.manifest_type
+            );
+        }
+
+        let api_key = match api_key {
+            Some(api_key) => api_key.to_owned(),
+            None => match env::var(CURSEFORGE_API_KEY_VAR) {
+                Ok(api_key) => api_key,
+                Err(_) => bail!("a CurseForge API key is required to install CurseForge modpacks, pass --curseforge-api-key or set {CURSEFORGE_API_KEY_VAR}"),
+            },
+        };
+
+        Ok(CurseForgeModpack {
+            path: path.to_owned(),
+            manifest,
+            api_key,
+        })
+    }
+
+    pub fn minecraft_version(&self) -> &str {
+        &self.manifest.minecraft.version
+    }
+
+    // returns the loader and the loader version
+    pub fn loader(&self) -> anyhow::Result<(ModLoader, Option<String>)> {
+        let mod_loader = self
+            .manifest
+            .minecraft
+            .mod_loaders
+            .iter()
+            .find(|mod_loader| mod_loader.primary)
+            .or_else(|| self.manifest.minecraft.mod_loaders.first());
+        let Some(mod_loader) = mod_loader else {
+            return Ok((ModLoader::Vanilla, None));
+        };
+        match mod_loader.id.split_once('-') {
+            Some(("fabric", version)) => Ok((ModLoader::Fabric, Some(version.to_owned()))),
+            _ => bail!("modpack uses unsupported loader {}", mod_loader.id),
+        }
+    }
+
+    pub fn install(&self, client: &Client, instance_path: &Path) -> anyhow::Result<()> {
+        status!(
+            "installing modpack {} {}",
+            self.manifest.name,
+            self.manifest.version
+        );
+        self.copy_overrides(instance_path)?;
+
+        let manifest_files: Vec<_> = self
+            .manifest
+            .files
+            .iter()
+            .filter(|file| file.required)
+            .collect();
+        if manifest_files.is_empty() {
+            return Ok(());
+        }
+
+        status!("fetching modpack files");
+        let files: CurseForgeResponse<Vec<CurseForgeFile>> = self.post(
+            client,
+            "mods/files",
+            &FileIdsRequest {
+                file_ids: manifest_files.iter().map(|file| file.file_id).collect(),
+            },
+        )?;
+        let files: HashMap<_, _> = files.data.into_iter().map(|file| (file.id, file)).collect();
+
+        let mods_path = instance_path.join("mods");
+        fs::create_dir_all(&mods_path).with_path_context(&mods_path)?;
+
+        let mut manual_downloads = Vec::new();
+        let mut downloads = Vec::new();
+        for manifest_file in manifest_files {
+            let Some(file) = files.get(&manifest_file.file_id) else {
+                bail!(
+                    "CurseForge file {} of project {} could not be found",
+                    manifest_file.file_id,
+                    manifest_file.project_id
+                );
+            };
+            let Some(download_url) = &file.download_url else {
+                manual_downloads.push(file);
+                continue;
+            };
+
+            // the name comes from the api, make sure it can't escape the mods folder
+            if Path::new(&file.file_name).file_name() != Some(file.file_name.as_ref()) {
+                bail!("CurseForge file has invalid name {}", file.file_name);
+            }
+            downloads.push((file, download_url));
+        }
+
+        let multi_progress = MultiProgress::new();
+        let active_bars = output::activate_bars(&multi_progress);
+        let results = parallel_map(downloads, |(file, download_url)| {
+            download_file(client, file, download_url, &mods_path, &multi_progress)
+        });
+        drop(active_bars);
+        for result in results {
+            result?;
+        }
+
+        if !manual_downloads.is_empty() {
+            self.print_manual_downloads(client, &manual_downloads, &mods_path)?;
+        }
+
+        Ok(())
+    }
+
+    fn copy_overrides(&self, instance_path: &Path) -> anyhow::Result<()> {
+        let file = File::open(&self.path).with_path_context(&self.path)?;
+        let mut archive = ZipArchive::new(file).with_path_context(&self.path)?;
+        let prefix = format!("{}/", self.manifest.overrides);
+
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).with_path_context(&self.path)?;
+            let Some(name) = entry.name().strip_prefix(&prefix) else {
+                continue;
+            };
+            if name.is_empty() {
+                continue;
+            }
+            let entry_path = archive_entry_path(instance_path, Path::new(name))?;
+            if entry.is_dir() {
+                fs::create_dir_all(&entry_path).with_path_context(&entry_path)?;
+                continue;
+            }
+            if let Some(parent) = entry_path.parent() {
+                fs::create_dir_all(parent).with_path_context(parent)?;
+            }
+            // when resuming an install, most overrides will already be there
+            let mut contents = Vec::with_capacity(entry.size() as usize);
+            entry
+                .read_to_end(&mut contents)
+                .with_path_context(&self.path)?;
+            if fs::read(&entry_path).is_ok_and(|existing| existing == contents) {
+                verbose!("keeping existing {}", entry_path.display());
+                continue;
+            }
+            fs::write(&entry_path, &contents).with_path_context(&entry_path)?;
+        }
+
+        Ok(())
+    }
+
+    // some authors opt out of third party downloads, those files have to be downloaded by hand
+    fn print_manual_downloads(
+        &self,
+        client: &Client,
+        files: &[&CurseForgeFile],
+        mods_path: &Path,
+    ) -> anyhow::Result<()> {
+        let projects: CurseForgeResponse<Vec<CurseForgeProject>> = self.post(
+            client,
+            "mods",
+            &ModIdsRequest {
+                mod_ids: files.iter().map(|file| file.mod_id).collect(),
+            },
+        )?;
+        let projects: HashMap<_, _> = projects
+            .data
+            .into_iter()
+            .map(|project| (project.id, project))
+            .collect();
+
+        eprintln!(
+            "warning: the following files can't be downloaded automatically, download them manually into {}:",
+            mods_path.display()
+        );
+        for file in files {
+            match projects.get(&file.mod_id) {
+                Some(project) => eprintln!(
+                    "  {} ({}): {}/files/{}",
+                    project.name, file.file_name, project.links.website_url, file.id
+                ),
+                None => eprintln!("  {} (project {})", file.file_name, file.mod_id),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn post<T, B>(&self, client: &Client, endpoint: &str, body: &B) -> anyhow::Result<T>
+    where
+        T: for<'de> Deserialize<'de>,
+        B: Serialize,
+    {
+        let url = format!("{CURSEFORGE_API_URL}/{endpoint}");
+        let response = client
+            .post(&url)
+            .header("x-api-key", &self.api_key)
+            .json(body)
+            .send()
+            .with_context(|| url.clone())?;
+        if !response.status().is_success() {
+            bail!(
+                "request to {} returned status code {}",
+                url,
+                response.status()
+            );
+        }
+        response.json().with_context(|| url.clone())
+    }
+}
+
+fn download_file(
+    client: &Client,
+    file: &CurseForgeFile,
+    download_url: &str,
+    mods_path: &Path,
+    multi_progress: &MultiProgress,
+) -> anyhow::Result<()> {
+    let mod_path = mods_path.join(&file.file_name);
+    let sha1 = file
+        .hashes
+        .iter()
+        .find(|hash| hash.algo == CURSEFORGE_SHA1_ALGO)
+        .and_then(|hash| hash.value.parse::<Sha1String>().ok());
+    // without a hash, go by the size to tell whether an interrupted install already got this file
+    if sha1.is_none()
+        && fs::metadata(&mod_path).is_ok_and(|metadata| metadata.len() == file.file_length)
+        && validate_jar(&mod_path).is_ok()
+    {
+        verbose!("keeping existing {}", mod_path.display());
+        return Ok(());
+    }
+    verbose!("downloading {download_url}");
+
+    let pb = add_progress_bar(
+        multi_progress,
+        file.file_length,
+        &mod_path,
+        format!("downloading {}", file.file_name),
+    );
+    match sha1 {
+        Some(sha1) => download_large_with_hash(
+            client,
+            download_url,
+            &mod_path,
+            HashAlgorithm::Sha1,
+            &sha1.inner,
+            |_| {},
+            |progress| pb.set_position(progress),
+        )?,
+        None => download_large(
+            client,
+            download_url,
+            &mod_path,
+            |_| {},
+            |progress| pb.set_position(progress),
+        )?,
+    }
+    if let Err(err) = validate_jar(&mod_path) {
+        let _ = fs::remove_file(&mod_path);
+        return Err(err);
+    }
+    finish_progress_bar(&pb, format!("downloaded {}", file.file_name));
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurseForgeManifest {
+    minecraft: CurseForgeMinecraft,
+    manifest_type: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    files: Vec<CurseForgeManifestFile>,
+    #[serde(default = "default_overrides")]
+    overrides: String,
+}
+
+fn default_overrides() -> String {
+    "overrides".to_owned()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurseForgeMinecraft {
+    version: String,
+    #[serde(default)]
+    mod_loaders: Vec<CurseForgeModLoader>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CurseForgeModLoader {
+    id: String,
+    #[serde(default)]
+    primary: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurseForgeManifestFile {
+    #[serde(rename = "projectID")]
+    project_id: u64,
+    #[serde(rename = "fileID")]
+    file_id: u64,
+    #[serde(default = "default_required")]
+    required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FileIdsRequest {
+    file_ids: Vec<u64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ModIdsRequest {
+    mod_ids: Vec<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CurseForgeResponse<T> {
+    data: T,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurseForgeFile {
+    id: u64,
+    mod_id: u64,
+    file_name: String,
+    file_length: u64,
+    // null when the author has disallowed third party downloads
+    download_url: Option<String>,
+    #[serde(default)]
+    hashes: Vec<CurseForgeHash>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CurseForgeHash {
+    value: String,
+    algo: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct CurseForgeProject {
+    id: u64,
+    name: String,
+    links: CurseForgeLinks,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurseForgeLinks {
+    website_url: String,
+}
@@ -1,68 +1,112 @@
-use crate::cli::select_from_list;
+use crate::cli::{confirm, select_from_list, AddCommand, SearchCommand};
 use crate::commands::add::AddModArgs;
 use crate::hashing::{HashAlgorithm, HashWithAlgorithm, Sha1String, Sha512String};
-use crate::instance::ModMetadata;
-use crate::ioutil::{download_large, download_large_with_hash};
+use crate::instance::ModKind;
 use crate::mod_loader::ModLoader;
-use crate::mod_provider::ModProvider;
-use crate::{make_progress_bar, ContextExt, LINE_ENDING};
+use crate::mod_provider::{ModProvider, PendingMod};
+use crate::mojang::{Manifest, VersionType};
+use crate::output::{hyperlink, status, verbose};
+use crate::{make_client, LINE_ENDING};
 use anyhow::{bail, Context};
+use clap::ValueEnum;
 use reqwest::blocking::Client;
 use reqwest::StatusCode;
 use serde::{Deserialize, Deserializer};
-use sha2::{Digest, Sha512};
 use std::cmp::Reverse;
 use std::fmt::{Display, Formatter};
-use std::fs::File;
-use std::{fs, io};
 use time::OffsetDateTime;
 use url::Url;
 
 const SEARCH_URL: &str = "https://api.modrinth.com/v2/search";
+const SEARCH_PAGE_SIZE: u32 = 20;
+const FABRIC_API_PROJECT_ID: &str = "P7dR8mSH";
+const FABRIC_API_SLUG: &str = "fabric-api";
+// changelogs can be very long, only show the start of each
+const CHANGELOG_PREVIEW_LINES: usize = 10;
 
 // TODO: download mod dependencies
-pub fn add_mod(args: AddModArgs<'_>) -> anyhow::Result<ModMetadata> {
+// works out what to download without downloading anything, so that bulk adds can download in parallel
+pub fn resolve_mod(args: AddModArgs<'_>) -> anyhow::Result<Vec<PendingMod>> {
+    let name = slug_from_url(args.name).unwrap_or_else(|| args.name.to_owned());
     let mut project = None;
-    if !args.command.force_search && is_valid_slug(&args.command.name) {
-        project = find_project(args.client, &args.command.name)?;
+    // the project endpoint accepts both, ids don't change when a project is renamed
+    if !args.command.force_search && (is_valid_slug(&name) || is_project_id(&name)) {
+        project = find_project(args.client, &name)?;
     }
     let perform_search = project.is_none();
     if perform_search {
-        let mut search_result = search_for_mods(
-            args.client,
-            &args.command.name,
-            args.instance_metadata.loader,
-        )?;
-        search_result
-            .hits
-            .sort_by_key(|result| result.server_side == SideRequirement::Unsupported);
-        let Some(chosen_hit) = select_from_list(
-            search_result.hits,
-            &format!(
-                "mod {} was not found, but similar results were found. Did you mean:",
-                args.command.name
-            ),
-        )?
-        else {
-            bail!(
-                "mod {} was not found, and no similar results were found.",
-                args.command.name
-            );
+        let mut hits = Vec::new();
+        let chosen_slug = loop {
+            let mut search_result = search_for_mods(
+                args.client,
+                &name,
+                &SearchFilters {
+                    loader: Some(args.instance_metadata.loader),
+                    game_version: None,
+                    project_type: Some(ProjectType::Mod),
+                },
+                hits.len(),
+                SEARCH_PAGE_SIZE,
+            )?;
+            // sort each page separately so that results already shown don't move around
+            search_result
+                .hits
+                .sort_by_key(|result| result.server_side == SideRequirement::Unsupported);
+            let remaining_hits = if search_result.hits.len() < search_result.limit {
+                0
+            } else {
+                search_result
+                    .total_hits
+                    .saturating_sub(search_result.offset + search_result.hits.len())
+            };
+            hits.extend(search_result.hits);
+
+            let mut choices: Vec<_> = hits.iter().map(SearchChoice::Hit).collect();
+            if remaining_hits > 0 {
+                choices.push(SearchChoice::ShowMore(remaining_hits));
+            }
+            match select_from_list(
+                choices,
+                &format!("mod {name} was not found, but similar results were found. Did you mean:"),
+                args.command.yes,
+            )? {
+                Some(SearchChoice::Hit(hit)) => break hit.slug.clone(),
+                Some(SearchChoice::ShowMore(_)) => {}
+                None => bail!("mod {name} was not found, and no similar results were found."),
+            }
         };
-        project = find_project(args.client, &chosen_hit.slug)?;
+        project = find_project(args.client, &chosen_slug)?;
     }
     let Some(project) = project else {
-        bail!("mod {} was not found", args.command.name);
+        bail!("mod {name} was not found");
     };
+    if matches!(
+        project.status,
+        ProjectStatus::Archived | ProjectStatus::Withheld
+    ) {
+        if args.command.allow_archived {
+            eprintln!(
+                "warning: mod {} is {}, its files may be unavailable",
+                project.slug, project.status
+            );
+        } else {
+            bail!(
+                "mod {} is {}, pass --allow-archived to install it anyway",
+                project.slug,
+                project.status
+            );
+        }
+    }
+
     let team_members = get_team_members(args.client, &project.slug)?;
     print_installing_message(&project, &team_members, perform_search);
 
-    if !project.game_versions.is_empty()
-        && !project
-            .game_versions
-            .contains(&args.instance_metadata.minecraft_version)
-    {
-        if args.command.skip_version_check {
+    let mut game_version = args.instance_metadata.minecraft_version.clone();
+    if !project.game_versions.is_empty() && !project.game_versions.contains(&game_version) {
+        if let Some(release) = nearest_supported_release(&args, &project.game_versions)? {
+            warn_snapshot_fallback(&game_version, &release);
+            game_version = release;
+        } else if args.command.skip_version_check {
             eprintln!(
                 "warning: mod does not support minecraft version {}",
                 args.instance_metadata.minecraft_version
@@ -75,18 +119,132 @@ pub fn add_mod(args: AddModArgs<'_>) -> anyhow::Result<ModMetadata> {
         }
     }
 
+    let loader = args.instance_metadata.loader;
+    let kind = match project.project_type {
+        ProjectType::Mod if is_datapack_only(&project, loader) => ModKind::Datapack,
+        ProjectType::Mod => ModKind::Mod,
+        ProjectType::Resourcepack => ModKind::ResourcePack,
+        ProjectType::Shader => ModKind::Shader,
+        ProjectType::Datapack => ModKind::Datapack,
+        ProjectType::Modpack | ProjectType::Unknown => {
+            bail!(
+                "{} is not a mod, resource pack, shader or datapack and cannot be installed",
+                project.slug
+            );
+        }
+    };
+    let Some(folder) = kind.install_folder(args.instance_path, loader)? else {
+        if kind == ModKind::ResourcePack && matches!(loader, ModLoader::Paper | ModLoader::Spigot) {
+            bail!("{loader} servers do not load resource packs from a local folder, set resource-pack in server.properties instead");
+        }
+        bail!("cannot install {kind}s on loader '{loader}'");
+    };
+    let target_folder = args.instance_path.join(&folder);
+
     let mut versions = get_project_versions(
         args.client,
         &project.slug,
-        args.instance_metadata.loader,
-        &args.instance_metadata.minecraft_version,
+        &modrinth_loaders(kind, loader),
+        &game_version,
         args.command.skip_version_check,
     )?;
-    if versions.is_empty() {
+    // the project may list the snapshot without having a version for this loader
+    if versions.is_empty() && game_version == args.instance_metadata.minecraft_version {
+        if let Some(release) = nearest_supported_release(&args, &project.game_versions)? {
+            warn_snapshot_fallback(&game_version, &release);
+            versions = get_project_versions(
+                args.client,
+                &project.slug,
+                &modrinth_loaders(kind, loader),
+                &release,
+                false,
+            )?;
+        }
+    }
+    let compatible_loaders = compatible_modrinth_loaders(loader);
+    if versions.is_empty()
+        && kind == ModKind::Mod
+        && !args.command.strict_loader
+        && !compatible_loaders.is_empty()
+    {
+        let compatible_loaders: Vec<_> = compatible_loaders
+            .iter()
+            .map(|&loader| loader.to_owned())
+            .collect();
+        versions = get_project_versions(
+            args.client,
+            &project.slug,
+            &compatible_loaders,
+            &game_version,
+            args.command.skip_version_check,
+        )?;
+        if !versions.is_empty() {
+            eprintln!(
+                "warning: mod has no {loader} versions, falling back to {} versions which {loader} can also load (pass --strict-loader to prevent this)",
+                compatible_loaders.join(" and ")
+            );
+        }
+    }
+    if versions.is_empty() && args.command.version.is_none() {
         bail!("mod does not have any matching versions");
     }
     versions.sort_by_key(|version| Reverse(version.date_published));
 
+    // a pinned version is installed whatever its channel
+    if let Some(pinned_version) = &args.command.version {
+        versions.retain(|version| {
+            version.version_number == *pinned_version || version.id == *pinned_version
+        });
+        // ids pinned elsewhere, such as by ferium, may be for another game version or loader
+        if versions.is_empty() && is_project_id(pinned_version) {
+            if let Some(version) = get_version(args.client, pinned_version)? {
+                if version.project_id != project.id {
+                    bail!(
+                        "version {pinned_version} belongs to a different project than mod {}",
+                        project.slug
+                    );
+                }
+                if !version.game_versions.contains(&game_version)
+                    || !version.loaders.iter().any(|version_loader| {
+                        modrinth_loaders(kind, loader).contains(version_loader)
+                    })
+                {
+                    eprintln!(
+                        "warning: version {} of mod {} is not marked as supporting {loader} {game_version}",
+                        version.version_number, project.slug
+                    );
+                }
+                versions.push(version);
+            }
+        }
+        if versions.is_empty() {
+            bail!(
+                "mod {} has no version {pinned_version} for {loader} {game_version}",
+                project.slug
+            );
+        }
+    }
+
+    // broaden the channel until something matches, alpha matches everything
+    let mut channel = args
+        .command
+        .version
+        .as_ref()
+        .map_or(args.command.channel, |_| VersionChannel::Alpha);
+    while !versions
+        .iter()
+        .any(|version| version.version_type <= channel)
+    {
+        let Some(broader_channel) = channel.broader() else {
+            break;
+        };
+        eprintln!(
+            "warning: mod has no {channel} versions, falling back to {broader_channel} versions"
+        );
+        channel = broader_channel;
+    }
+    versions.retain(|version| version.version_type <= channel);
+
     let Some((version, file)) = versions
         .iter()
         .flat_map(|version| version.files.iter().map(move |file| (version, file)))
@@ -95,13 +253,12 @@ pub fn add_mod(args: AddModArgs<'_>) -> anyhow::Result<ModMetadata> {
         bail!("mod does not have any matching files");
     };
 
-    let Some(mods_folder) = args.instance_metadata.loader.mods_folder() else {
-        bail!(
-            "cannot install mods on loader '{}'",
-            args.instance_metadata.loader
-        );
-    };
-    let mods_folder = args.instance_path.join(mods_folder);
+    verbose!(
+        "using {} version {} ({})",
+        project.slug,
+        version.version_number,
+        file.url
+    );
 
     let existing_mod = args
         .instance_metadata
@@ -121,14 +278,17 @@ pub fn add_mod(args: AddModArgs<'_>) -> anyhow::Result<ModMetadata> {
             _ => false,
         };
         if existing_mod.file_name == file.filename && hash_matches {
-            bail!("mod is already up-to-date");
+            if !args.command.reinstall {
+                bail!("{kind} is already up-to-date");
+            }
+            verbose!("{kind} is already up-to-date, reinstalling it");
         }
     }
 
     for m in &args.instance_metadata.mods {
-        if m.id != project.id && m.file_name == file.filename {
+        if m.id != project.id && m.kind == kind && m.file_name == file.filename {
             bail!(
-                "mod conflicts with existing mod {} ({}), which also has the filename '{}'",
+                "{kind} conflicts with existing {kind} {} ({}), which also has the filename '{}'",
                 m.id,
                 m.name,
                 m.file_name
@@ -136,110 +296,151 @@ pub fn add_mod(args: AddModArgs<'_>) -> anyhow::Result<ModMetadata> {
         }
     }
 
-    let (mut algorithm, mut hash) = match &file.hashes {
+    if args.command.show_changelog {
+        print_changelogs(&versions, version, existing_mod.map(|m| &m.file_name[..]));
+    }
+
+    let hash = match &file.hashes {
         ProjectFileHashes {
             sha512: Some(sha512),
             ..
-        } => (
-            Some(HashAlgorithm::Sha512),
-            Some(sha512.inner.to_vec().into_boxed_slice()),
-        ),
+        } => Some(HashWithAlgorithm {
+            algorithm: HashAlgorithm::Sha512,
+            hash: sha512.inner.to_vec().into_boxed_slice(),
+        }),
         ProjectFileHashes {
             sha1: Some(sha1), ..
-        } => (
-            Some(HashAlgorithm::Sha1),
-            Some(sha1.inner.to_vec().into_boxed_slice()),
-        ),
-        _ => (None, None),
+        } => Some(HashWithAlgorithm {
+            algorithm: HashAlgorithm::Sha1,
+            hash: sha1.inner.to_vec().into_boxed_slice(),
+        }),
+        _ => None,
     };
 
-    fs::create_dir_all(&mods_folder).with_path_context(&mods_folder)?;
-    let mod_path = mods_folder.join(&file.filename);
-
-    if let (Some(algorithm), Some(hash)) = (algorithm, &hash) {
-        let pb = make_progress_bar(
-            file.size,
-            format!("downloading {} {}", project.slug, version.name),
-        );
-        download_large_with_hash(
-            args.client,
-            file.url.clone(),
-            &mod_path,
-            algorithm,
-            hash,
-            |_| {},
-            |progress| pb.set_position(progress),
-        )?;
-        pb.finish_with_message(format!("downloaded {} {}", project.slug, version.name));
-    } else {
-        let pb = make_progress_bar(
-            file.size,
-            format!("downloading {} {}", project.slug, version.name),
-        );
-        download_large(
-            args.client,
-            file.url.clone(),
-            &mod_path,
-            |_| {},
-            |progress| pb.set_position(progress),
-        )?;
-        let mut digest = Sha512::new();
-        io::copy(
-            &mut File::open(&mod_path).with_path_context(&mod_path)?,
-            &mut digest,
-        )
-        .with_path_context(&mod_path)?;
-        algorithm = Some(HashAlgorithm::Sha512);
-        hash = Some(digest.finalize().to_vec().into_boxed_slice());
-        pb.finish_with_message(format!("downloaded {} {}", project.slug, version.name));
-    }
+    // many fabric mods declare fabric api as optional even though they don't work without it
+    let uses_fabric_api = loader == ModLoader::Fabric
+        && project.id != FABRIC_API_PROJECT_ID
+        && version.dependencies.iter().any(|dependency| {
+            dependency.project_id.as_deref() == Some(FABRIC_API_PROJECT_ID)
+                && dependency.dependency_type != ProjectDependencyType::Incompatible
+        });
 
-    if let Some(existing_mod) = existing_mod {
-        if existing_mod.file_name != file.filename {
-            let old_mod_file = mods_folder.join(&existing_mod.file_name);
-            if let Err(err) = fs::remove_file(&old_mod_file) {
-                if err.kind() != io::ErrorKind::NotFound {
-                    return Err(err).with_path_context(&old_mod_file);
-                }
-            }
-        }
-    }
-
-    Ok(ModMetadata {
+    let replaces = existing_mod
+        .filter(|existing_mod| existing_mod.file_name != file.filename)
+        .map(|existing_mod| {
+            let existing_folder = existing_mod.folder(loader).map_or_else(
+                || target_folder.clone(),
+                |existing_folder| args.instance_path.join(existing_folder),
+            );
+            existing_folder.join(&existing_mod.file_name)
+        });
+    let mut pending_mods = vec![PendingMod {
+        label: format!("{} {}", project.slug, version.name),
         name: project.slug,
         id: project.id,
         file_name: file.filename.to_owned(),
-        hash: HashWithAlgorithm {
-            algorithm: algorithm.unwrap(),
-            hash: hash.unwrap(),
-        },
+        url: file.url.clone(),
+        size: file.size,
+        hash,
+        version: Some(version.version_number.clone()),
         provider: ModProvider::Modrinth,
-    })
+        kind,
+        target_folder,
+        // datapacks follow the world, record where this one went in case level-name changes
+        folder: (kind == ModKind::Datapack).then_some(folder),
+        replaces,
+    }];
+
+    if uses_fabric_api
+        && !args.command.no_deps
+        && !args
+            .instance_metadata
+            .mods
+            .iter()
+            .any(|m| m.provider == ModProvider::Modrinth && m.id == FABRIC_API_PROJECT_ID)
+        && !args
+            .pending_mods
+            .iter()
+            .any(|m| m.provider == ModProvider::Modrinth && m.id == FABRIC_API_PROJECT_ID)
+        && confirm(
+            "this mod uses fabric api, which is not installed. Install it?",
+            true,
+            args.command.yes,
+        )?
+    {
+        let fabric_api_command = AddCommand {
+            version: None,
+            force_search: false,
+            no_deps: true,
+            ..args.command.clone()
+        };
+        pending_mods.extend(resolve_mod(AddModArgs {
+            command: &fabric_api_command,
+            name: FABRIC_API_SLUG,
+            ..args
+        })?);
+    }
+
+    Ok(pending_mods)
+}
+
+fn modrinth_loaders(kind: ModKind, loader: ModLoader) -> Vec<String> {
+    match kind {
+        ModKind::Mod => vec![loader.to_string()],
+        ModKind::ResourcePack => vec!["minecraft".to_owned()],
+        ModKind::Shader => vec!["iris".to_owned(), "canvas".to_owned()],
+        ModKind::Datapack => vec!["datapack".to_owned()],
+    }
+}
+
+// modrinth lists datapacks as mods with a datapack loader, some also ship a mod build of the same project
+fn is_datapack_only(project: &Project, loader: ModLoader) -> bool {
+    let compatible_loaders = compatible_modrinth_loaders(loader);
+    let has_loader = |name: &str| {
+        project
+            .loaders
+            .iter()
+            .any(|project_loader| project_loader.to_string() == name)
+    };
+    has_loader("datapack")
+        && !has_loader(&loader.to_string())
+        && !compatible_loaders.iter().any(|&loader| has_loader(loader))
+}
+
+// loaders whose mods also run on this loader, tried when nothing is tagged with the loader itself
+fn compatible_modrinth_loaders(loader: ModLoader) -> &'static [&'static str] {
+    match loader {
+        ModLoader::Paper => &["spigot", "bukkit"],
+        ModLoader::Spigot => &["bukkit"],
+        ModLoader::Vanilla | ModLoader::Fabric | ModLoader::Bedrock => &[],
+    }
+}
+
+// accepts links to a project page, e.g. https://modrinth.com/mod/sodium/versions
+fn slug_from_url(name: &str) -> Option<String> {
+    let url = Url::parse(name).ok()?;
+    if !matches!(url.host_str(), Some("modrinth.com" | "www.modrinth.com")) {
+        return None;
+    }
+    url.path_segments()?
+        .nth(1)
+        .filter(|slug| !slug.is_empty())
+        .map(ToOwned::to_owned)
 }
 
 fn is_valid_slug(slug: &str) -> bool {
     fn is_valid_slug_char(char: u8) -> bool {
-        char.is_ascii_alphanumeric()
-            || matches!(
-                char,
-                b'!' | b'@'
-                    | b'$'
-                    | b'('
-                    | b')'
-                    | b'`'
-                    | b'.'
-                    | b'+'
-                    | b','
-                    | b'"'
-                    | b'\\'
-                    | b'-'
-                    | b'\''
-            )
+        char.is_ascii_lowercase() || char.is_ascii_digit() || matches!(char, b'-' | b'_')
     }
 
     (3..=64).contains(&slug.len()) && slug.bytes().all(is_valid_slug_char)
 }
 
+// modrinth ids are 8 base62 characters, unlike slugs they can contain uppercase letters
+fn is_project_id(id: &str) -> bool {
+    id.len() == 8 && id.bytes().all(|char| char.is_ascii_alphanumeric())
+}
+
 fn find_project(client: &Client, slug: &str) -> anyhow::Result<Option<Project>> {
     let url = format!(
         "https://api.modrinth.com/v2/project/{}",
@@ -275,19 +476,71 @@ fn get_team_members(client: &Client, slug: &str) -> anyhow::Result<Vec<TeamMembe
     response.json().with_context(|| url.clone())
 }
 
+pub fn search(command: &SearchCommand) -> anyhow::Result<()> {
+    let client = make_client()?;
+    let search_result = search_for_mods(
+        &client,
+        &command.query,
+        &SearchFilters {
+            loader: command.loader,
+            game_version: command.version.as_deref(),
+            project_type: command.project_type,
+        },
+        0,
+        command.limit,
+    )?;
+
+    if search_result.hits.is_empty() {
+        eprintln!("no results found for {}", command.query);
+        return Ok(());
+    }
+
+    for (index, hit) in search_result.hits.iter().enumerate() {
+        println!("{}. {hit}", index + 1);
+        println!("   server side: {}", hit.server_side);
+    }
+    if search_result.total_hits > search_result.hits.len() {
+        status!(
+            "showing {} of {} results",
+            search_result.hits.len(),
+            search_result.total_hits
+        );
+    }
+
+    Ok(())
+}
+
+struct SearchFilters<'a> {
+    loader: Option<ModLoader>,
+    game_version: Option<&'a str>,
+    project_type: Option<ProjectType>,
+}
+
 fn search_for_mods(
     client: &Client,
-    slug: &str,
-    loader: ModLoader,
+    query: &str,
+    filters: &SearchFilters<'_>,
+    offset: usize,
+    limit: u32,
 ) -> anyhow::Result<SearchResults> {
+    let mut facets = Vec::new();
+    if let Some(loader) = filters.loader {
+        facets.push([format!("categories:{loader}")]);
+    }
+    if let Some(game_version) = filters.game_version {
+        facets.push([format!("versions:{game_version}")]);
+    }
+    if let Some(project_type) = filters.project_type {
+        facets.push([format!("project_type:{project_type}")]);
+    }
+
     let response = client
         .get(SEARCH_URL)
         .query(&[
-            ("query", slug),
-            (
-                "facets",
-                &format!("[[\"categories:{loader}\"],[\"project_type:mod\"]]"),
-            ),
+            ("query", query),
+            ("facets", &serde_json::to_string(&facets)?),
+            ("limit", &limit.to_string()),
+            ("offset", &offset.to_string()),
         ])
         .send()
         .context(SEARCH_URL)?;
@@ -301,10 +554,75 @@ fn search_for_mods(
     response.json().context(SEARCH_URL)
 }
 
+// only used with --include-snapshots or --snapshot-as-release when the instance is on a snapshot
+fn nearest_supported_release(
+    args: &AddModArgs<'_>,
+    supported_versions: &[String],
+) -> anyhow::Result<Option<String>> {
+    if !args.command.include_snapshots && !args.command.snapshot_as_release {
+        return Ok(None);
+    }
+
+    let manifest = Manifest::download(args.client, &args.cache_dir.join("version_manifest.json"))?;
+    let Some(current) = manifest
+        .versions
+        .iter()
+        .find(|version| version.id == args.instance_metadata.minecraft_version)
+    else {
+        return Ok(None);
+    };
+    if !matches!(current.typ, VersionType::Snapshot) {
+        return Ok(None);
+    }
+
+    if args.command.snapshot_as_release {
+        let target_release = manifest
+            .target_release(current)
+            .filter(|release| supported_versions.contains(release));
+        if target_release.is_some() || !args.command.include_snapshots {
+            return Ok(target_release);
+        }
+    }
+
+    Ok(manifest
+        .versions
+        .iter()
+        .filter(|version| {
+            matches!(version.typ, VersionType::Release) && supported_versions.contains(&version.id)
+        })
+        .min_by_key(|version| (version.release_time - current.release_time).abs())
+        .map(|version| version.id.clone()))
+}
+
+fn warn_snapshot_fallback(snapshot: &str, release: &str) {
+    eprintln!(
+        "warning: mod does not support snapshot {snapshot}, using versions for {release} instead"
+    );
+}
+
+fn get_version(client: &Client, id: &str) -> anyhow::Result<Option<ProjectVersion>> {
+    let url = format!(
+        "https://api.modrinth.com/v2/version/{}",
+        urlencoding::encode(id)
+    );
+    let response = client.get(&url).send().with_context(|| url.clone())?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    } else if !response.status().is_success() {
+        bail!(
+            "request to {} returned status code {}",
+            url,
+            response.status()
+        );
+    }
+
+    response.json().map(Some).with_context(|| url.clone())
+}
+
 fn get_project_versions(
     client: &Client,
     slug: &str,
-    loader: ModLoader,
+    loaders: &[String],
     mc_version: &str,
     skip_version_check: bool,
 ) -> anyhow::Result<Vec<ProjectVersion>> {
@@ -314,7 +632,7 @@ fn get_project_versions(
     );
     let mut request_builder = client
         .get(&url)
-        .query(&[("loaders", &format!("[\"{loader}\"]"))]);
+        .query(&[("loaders", &serde_json::to_string(loaders)?)]);
     if !skip_version_check {
         request_builder = request_builder.query(&[(
             "game_versions",
@@ -335,37 +653,90 @@ fn get_project_versions(
     response.json().with_context(|| url.clone())
 }
 
+// versions are sorted newest first, prints from the chosen version down to the installed one
+fn print_changelogs(
+    versions: &[ProjectVersion],
+    version: &ProjectVersion,
+    installed_file_name: Option<&str>,
+) {
+    let Some(start) = versions
+        .iter()
+        .position(|v| v.version_number == version.version_number)
+    else {
+        return;
+    };
+    // only the chosen version when the installed version isn't in the list
+    let end = installed_file_name
+        .and_then(|installed| {
+            versions
+                .iter()
+                .position(|v| v.files.iter().any(|file| file.filename == installed))
+        })
+        .filter(|&end| end > start)
+        .unwrap_or(start + 1);
+    for v in &versions[start..end] {
+        let changelog = v.changelog.as_deref().unwrap_or("").trim();
+        if changelog.is_empty() {
+            status!("changelog for {}: none", v.version_number);
+            continue;
+        }
+        status!("changelog for {}:", v.version_number);
+        let lines: Vec<_> = changelog.lines().collect();
+        for line in lines.iter().take(CHANGELOG_PREVIEW_LINES) {
+            status!("  {line}");
+        }
+        if lines.len() > CHANGELOG_PREVIEW_LINES {
+            status!(
+                "  ... ({} more lines)",
+                lines.len() - CHANGELOG_PREVIEW_LINES
+            );
+        }
+    }
+}
+
 fn print_installing_message(
     project: &Project,
     team_members: &[TeamMember],
     performed_search: bool,
 ) {
-    eprintln!(
+    status!(
         "installing {} ({}, {})",
-        project.slug, project.id, project.title
+        project.slug,
+        project.id,
+        project.title
+    );
+    status!("server side: {}", project.server_side);
+    // popularity helps tell the real project apart from a similarly named one
+    status!(
+        "downloads: {}, followers: {}",
+        project.downloads,
+        project.followers
     );
+    if let Some(icon_url) = &project.icon_url {
+        status!("icon: {}", hyperlink(icon_url.as_str()));
+    }
     if !team_members.is_empty() {
-        eprintln!("by:");
+        status!("by:");
         for team_member in team_members {
-            eprintln!("- {} ({})", team_member.user.username, team_member.role);
+            status!("- {} ({})", team_member.user.username, team_member.role);
         }
     }
     if project.game_versions.is_empty() {
-        eprintln!("no supported minecraft versions");
+        status!("no supported minecraft versions");
     } else {
-        eprintln!("supported minecraft versions:");
+        status!("supported minecraft versions:");
         for version in &project.game_versions {
-            eprintln!("- {}", version);
+            status!("- {}", version);
         }
     }
     if !project.loaders.is_empty() {
-        eprintln!("supported loaders:");
+        status!("supported loaders:");
         for loader in &project.loaders {
-            eprintln!("- {}", loader);
+            status!("- {}", loader);
         }
     }
     if !performed_search {
-        eprintln!("if this is not the right mod, force a search with -s");
+        status!("if this is not the right mod, force a search with -s");
     }
 }
 
@@ -373,14 +744,21 @@ fn print_installing_message(
 struct Project {
     slug: String,
     title: String,
-    description: String,
     server_side: SideRequirement,
     project_type: ProjectType,
+    #[serde(default)]
+    status: ProjectStatus,
     id: String,
     #[serde(default)]
     game_versions: Vec<String>,
     #[serde(default)]
     loaders: Vec<ModrinthLoader>,
+    #[serde(default)]
+    icon_url: Option<Url>,
+    #[serde(default)]
+    downloads: u64,
+    #[serde(default)]
+    followers: u64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -392,13 +770,14 @@ struct TeamMember {
 #[derive(Debug, Deserialize)]
 struct User {
     username: String,
-    #[serde(default)]
-    name: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct SearchResults {
     pub hits: Vec<SearchHit>,
+    pub offset: usize,
+    pub limit: usize,
+    pub total_hits: usize,
 }
 
 #[derive(Debug, Deserialize)]
@@ -407,10 +786,7 @@ struct SearchHit {
     title: String,
     description: String,
     server_side: SideRequirement,
-    project_type: ProjectType,
-    project_id: String,
     author: String,
-    versions: Vec<String>,
 }
 
 impl Display for SearchHit {
@@ -421,11 +797,27 @@ impl Display for SearchHit {
         }
         if self.server_side == SideRequirement::Unsupported {
             write!(f, "{}   warning: client-side only", LINE_ENDING)?;
+        } else if self.server_side == SideRequirement::Optional {
+            write!(f, "{}   note: optional on the server", LINE_ENDING)?;
         }
         Ok(())
     }
 }
 
+enum SearchChoice<'a> {
+    Hit(&'a SearchHit),
+    ShowMore(usize),
+}
+
+impl Display for SearchChoice<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hit(hit) => Display::fmt(hit, f),
+            Self::ShowMore(remaining) => write!(f, "show more ({remaining} remaining)"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "snake_case")]
 enum SideRequirement {
@@ -436,17 +828,77 @@ enum SideRequirement {
     Unknown,
 }
 
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+impl Display for SideRequirement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Required => "required",
+            Self::Optional => "optional",
+            Self::Unsupported => "unsupported",
+            Self::Unknown => "unknown",
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, Deserialize)]
 #[serde(rename_all = "snake_case")]
-enum ProjectType {
+pub enum ProjectType {
     Mod,
     Modpack,
     Resourcepack,
     Shader,
+    Datapack,
     #[serde(other)]
+    #[value(skip)]
     Unknown,
 }
 
+impl Display for ProjectType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Mod => "mod",
+            Self::Modpack => "modpack",
+            Self::Resourcepack => "resourcepack",
+            Self::Shader => "shader",
+            Self::Datapack => "datapack",
+            Self::Unknown => "unknown",
+        })
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ProjectStatus {
+    Approved,
+    Archived,
+    Rejected,
+    Draft,
+    Unlisted,
+    Processing,
+    Withheld,
+    Scheduled,
+    Private,
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+impl Display for ProjectStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Approved => "approved",
+            Self::Archived => "archived",
+            Self::Rejected => "rejected",
+            Self::Draft => "a draft",
+            Self::Unlisted => "unlisted",
+            Self::Processing => "processing",
+            Self::Withheld => "withheld",
+            Self::Scheduled => "scheduled",
+            Self::Private => "private",
+            Self::Unknown => "in an unknown state",
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Deserialize)]
 #[serde(untagged)]
 enum ModrinthLoader {
@@ -465,27 +917,60 @@ impl Display for ModrinthLoader {
 
 #[derive(Debug, Deserialize)]
 struct ProjectVersion {
+    id: String,
+    project_id: String,
+    #[serde(default)]
+    game_versions: Vec<String>,
+    #[serde(default)]
+    loaders: Vec<String>,
     name: String,
     version_number: String,
+    #[serde(default)]
+    changelog: Option<String>,
     dependencies: Vec<ProjectDependency>,
-    game_versions: Vec<String>,
     #[serde(with = "time::serde::iso8601")]
     date_published: OffsetDateTime,
+    version_type: VersionChannel,
     files: Vec<ProjectFile>,
 }
 
+// ordered from most to least stable
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionChannel {
+    Release,
+    Beta,
+    Alpha,
+}
+
+impl VersionChannel {
+    fn broader(&self) -> Option<VersionChannel> {
+        match self {
+            Self::Release => Some(Self::Beta),
+            Self::Beta => Some(Self::Alpha),
+            Self::Alpha => None,
+        }
+    }
+}
+
+impl Display for VersionChannel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Release => "release",
+            Self::Beta => "beta",
+            Self::Alpha => "alpha",
+        })
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct ProjectDependency {
-    #[serde(default)]
-    version_id: Option<String>,
     #[serde(default)]
     project_id: Option<String>,
-    #[serde(default)]
-    file_name: Option<String>,
     dependency_type: ProjectDependencyType,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "snake_case")]
 enum ProjectDependencyType {
     Required,
@@ -534,3 +1019,37 @@ where
 {
     Option::<T>::deserialize(deserializer).map(|value| value.unwrap_or_default())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_slugs() {
+        assert!(is_valid_slug("sodium"));
+        assert!(is_valid_slug("fabric-api"));
+        assert!(is_valid_slug("no_chat_reports"));
+        assert!(is_valid_slug("ae2"));
+    }
+
+    #[test]
+    fn uppercase_is_not_a_slug() {
+        assert!(!is_valid_slug("Sodium"));
+        assert!(!is_valid_slug("FABRIC-API"));
+    }
+
+    #[test]
+    fn search_queries_are_not_slugs() {
+        assert!(!is_valid_slug("fabric api"));
+        assert!(!is_valid_slug("\"sodium\""));
+        assert!(!is_valid_slug("it's"));
+    }
+
+    #[test]
+    fn slug_length_bounds() {
+        assert!(!is_valid_slug("ab"));
+        assert!(is_valid_slug("abc"));
+        assert!(is_valid_slug(&"a".repeat(64)));
+        assert!(!is_valid_slug(&"a".repeat(65)));
+    }
+}
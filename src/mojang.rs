@@ -1,6 +1,6 @@
-use crate::hashing::{HashAlgorithm, Sha1String};
+use crate::hashing::{HashAlgorithm, HashWithAlgorithm, Sha1String};
 use crate::ioutil::JsonDeserializer;
-use crate::{ioutil, ContextExt};
+use crate::{ioutil, mirror, ContextExt};
 use anyhow::{bail, Context};
 use reqwest::blocking::Client;
 use serde::Deserialize;
@@ -20,7 +20,45 @@ pub struct Manifest {
 
 impl Manifest {
     pub fn download(client: &Client, file: &Path) -> anyhow::Result<Manifest> {
-        ioutil::download_with_etag(client, MANIFEST_URL, file, JsonDeserializer::new())
+        let url = mirror::mojang_url(&Url::parse(MANIFEST_URL)?);
+        ioutil::download_with_etag(client, url.as_str(), file, JsonDeserializer::new())
+    }
+
+    // resolves latest, latest-release and latest-snapshot, other versions are returned as they are
+    pub fn resolve_version_alias<'a>(&'a self, version: &'a str) -> anyhow::Result<&'a str> {
+        match version {
+            "latest" | "latest-release" => Ok(&self.latest.release),
+            "latest-snapshot" => Ok(&self.latest.snapshot),
+            _ if version.starts_with("latest") => bail!(
+                "unknown version alias {version}, expected latest, latest-release or latest-snapshot"
+            ),
+            _ => Ok(version),
+        }
+    }
+
+    // the release a snapshot leads up to, e.g. 1.21.2 for 24w40a or 1.21.2-rc1. None if it isn't out yet
+    pub fn target_release(&self, version: &ManifestVersion) -> Option<String> {
+        if matches!(version.typ, VersionType::Release) {
+            return Some(version.id.clone());
+        }
+        // pre-releases and release candidates are named after their release, old ones with " Pre-Release 1"
+        if let Some((release, _)) = version
+            .id
+            .split_once("-pre")
+            .or_else(|| version.id.split_once("-rc"))
+            .or_else(|| version.id.split_once(" Pre-Release"))
+        {
+            return Some(release.to_owned());
+        }
+        // weekly snapshots lead to the next release after them
+        self.versions
+            .iter()
+            .filter(|release| {
+                matches!(release.typ, VersionType::Release)
+                    && release.release_time > version.release_time
+            })
+            .min_by_key(|release| release.release_time)
+            .map(|release| release.id.clone())
     }
 }
 
@@ -46,33 +84,42 @@ impl ManifestVersion {
     pub fn download(&self, client: &Client, file: &Path) -> anyhow::Result<Version> {
         if let Ok(file_contents) = fs::read(file) {
             if *Sha1::digest(&file_contents) == self.sha1.inner {
+                ioutil::record_download(&self.url, file, Some(self.verified_hash()))?;
                 return serde_json::from_slice(&file_contents).with_path_context(file);
             }
         }
 
-        let response = client
-            .get(self.url.clone())
+        let url = mirror::mojang_url(&self.url);
+        let response = mirror::authorize(client.get(url.clone()), &url)
             .send()
-            .with_context(|| self.url.clone())?;
+            .with_context(|| url.clone())?;
         if !response.status().is_success() {
             bail!(
                 "request to {} returned status code {}",
-                self.url,
+                url,
                 response.status()
             );
         }
-        let file_contents = response.bytes().with_context(|| self.url.clone())?.to_vec();
+        let file_contents = response.bytes().with_context(|| url.clone())?.to_vec();
         if *Sha1::digest(&file_contents) != self.sha1.inner {
             bail!(
                 "file downloaded from {} did not match the expected hash",
-                self.url
+                url
             );
         }
 
         fs::write(file, &file_contents)?;
+        ioutil::record_download(&url, file, Some(self.verified_hash()))?;
 
         serde_json::from_slice(&file_contents).with_path_context(file)
     }
+
+    fn verified_hash(&self) -> HashWithAlgorithm {
+        HashWithAlgorithm {
+            algorithm: HashAlgorithm::Sha1,
+            hash: self.sha1.inner.into(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -114,7 +161,7 @@ impl VersionDownload {
     ) -> anyhow::Result<()> {
         ioutil::download_large_with_hash(
             client,
-            self.url.clone(),
+            mirror::mojang_url(&self.url),
             path,
             HashAlgorithm::Sha1,
             &self.sha1.inner,
@@ -128,4 +175,21 @@ impl VersionDownload {
 #[serde(rename_all = "camelCase")]
 pub struct JavaVersion {
     pub major_version: u32,
+    // the name of the runtime the launcher downloads, e.g. java-runtime-gamma
+    #[serde(default)]
+    pub component: Option<String>,
+}
+
+impl JavaVersion {
+    // some versions bump the runtime component without bumping the major version, so take the newer of the two
+    pub fn required_major_version(&self) -> u32 {
+        let component_major_version = match self.component.as_deref() {
+            Some("jre-legacy") => 8,
+            Some("java-runtime-alpha") => 16,
+            Some("java-runtime-beta" | "java-runtime-gamma" | "java-runtime-gamma-snapshot") => 17,
+            Some("java-runtime-delta") => 21,
+            _ => 0,
+        };
+        self.major_version.max(component_major_version)
+    }
 }
@@ -0,0 +1,113 @@
+use indicatif::MultiProgress;
+use std::fmt::Arguments;
+use std::io;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+static NO_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_no_progress(no_progress: bool) {
+    NO_PROGRESS.store(no_progress, Ordering::Relaxed);
+}
+
+static JSON_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_json_progress(json_progress: bool) {
+    JSON_PROGRESS.store(json_progress, Ordering::Relaxed);
+}
+
+pub fn json_progress() -> bool {
+    JSON_PROGRESS.load(Ordering::Relaxed)
+}
+
+// animated bars would fill log files with redraws when stderr is redirected
+pub fn progress_bars_enabled() -> bool {
+    !NO_PROGRESS.load(Ordering::Relaxed) && io::stderr().is_terminal()
+}
+
+// set while parallel downloads draw their bars, lines printed from the download threads go above them
+static ACTIVE_BARS: Mutex<Option<MultiProgress>> = Mutex::new(None);
+
+pub struct ActiveBarsGuard;
+
+impl Drop for ActiveBarsGuard {
+    fn drop(&mut self) {
+        *ACTIVE_BARS.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+pub fn activate_bars(multi_progress: &MultiProgress) -> ActiveBarsGuard {
+    *ACTIVE_BARS.lock().unwrap_or_else(PoisonError::into_inner) = Some(multi_progress.clone());
+    ActiveBarsGuard
+}
+
+// printing straight to stderr while bars are drawn tears them
+pub fn print_line(args: Arguments<'_>) {
+    // keep stderr parseable for frontends reading json progress events
+    if json_progress() {
+        eprintln!(
+            "{}",
+            serde_json::json!({ "event": "status", "message": args.to_string() })
+        );
+        return;
+    }
+    let active_bars = ACTIVE_BARS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    match active_bars {
+        Some(multi_progress) => multi_progress.suspend(|| eprintln!("{args}")),
+        None => eprintln!("{args}"),
+    }
+}
+
+// status lines about what is being done, hidden by --quiet. Errors, warnings and prompts should use eprintln! directly
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::output::verbosity() >= $crate::output::Verbosity::Normal {
+            $crate::output::print_line(format_args!($($arg)*));
+        }
+    };
+}
+
+// extra detail, only shown with --verbose
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::output::verbosity() >= $crate::output::Verbosity::Verbose {
+            $crate::output::print_line(format_args!($($arg)*));
+        }
+    };
+}
+
+// terminals that support OSC 8 make the text clickable, others just show the url
+pub fn hyperlink(url: &str) -> String {
+    if !io::stderr().is_terminal() {
+        return url.to_owned();
+    }
+    format!("\x1b]8;;{url}\x1b\\{url}\x1b]8;;\x1b\\")
+}
+
+pub(crate) use status;
+pub(crate) use verbose;
@@ -0,0 +1,103 @@
+use crate::{ioutil, ContextExt, LINE_ENDING};
+use anyhow::bail;
+use std::fs;
+use std::path::Path;
+
+const DEFAULT_LEVEL_NAME: &str = "world";
+
+pub fn set_properties(path: &Path, properties: &[(String, String)]) -> anyhow::Result<()> {
+    if properties.is_empty() {
+        return Ok(());
+    }
+
+    // later values for the same key override earlier ones
+    let mut new_properties: Vec<(&str, &str, bool)> = Vec::new();
+    for (key, value) in properties {
+        match new_properties.iter_mut().find(|(k, _, _)| k == key) {
+            Some(property) => property.1 = value,
+            None => new_properties.push((key, value, false)),
+        }
+    }
+
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if ioutil::is_not_found(&err) => String::new(),
+        Err(err) => return Err(err).with_path_context(path),
+    };
+
+    let mut result = String::with_capacity(contents.len());
+    for line in contents.lines() {
+        let new_property = property_key(line)
+            .and_then(|key| new_properties.iter_mut().find(|(k, _, _)| *k == key));
+        match new_property {
+            Some((key, value, written)) => {
+                if *written {
+                    continue;
+                }
+                result.push_str(&format!("{key}={value}"));
+                *written = true;
+            }
+            None => result.push_str(line),
+        }
+        result.push_str(LINE_ENDING);
+    }
+
+    for (key, value, written) in new_properties {
+        if !written {
+            result.push_str(&format!("{key}={value}"));
+            result.push_str(LINE_ENDING);
+        }
+    }
+
+    fs::write(path, result).with_path_context(path)
+}
+
+// the world folder name, relative to the instance
+pub fn get_level_name(instance_path: &Path) -> anyhow::Result<String> {
+    let properties_path = instance_path.join("server.properties");
+    Ok(get_property(&properties_path, "level-name")?
+        .filter(|level_name| !level_name.is_empty())
+        .unwrap_or_else(|| DEFAULT_LEVEL_NAME.to_owned()))
+}
+
+pub fn get_property(path: &Path, key: &str) -> anyhow::Result<Option<String>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if ioutil::is_not_found(&err) => return Ok(None),
+        Err(err) => return Err(err).with_path_context(path),
+    };
+
+    // later values for the same key override earlier ones
+    let mut result = None;
+    for line in contents.lines() {
+        if property_key(line) != Some(key) {
+            continue;
+        }
+        let rest = line.trim_start()[key.len()..].trim_start();
+        let value = rest.strip_prefix(['=', ':']).unwrap_or(rest).trim_start();
+        result = Some(value.to_owned());
+    }
+    Ok(result)
+}
+
+fn property_key(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+        return None;
+    }
+    let end = line
+        .find(|c: char| c == '=' || c == ':' || c.is_whitespace())
+        .unwrap_or(line.len());
+    Some(&line[..end])
+}
+
+pub fn parse_property(str: &str) -> anyhow::Result<(String, String)> {
+    let Some((key, value)) = str.split_once('=') else {
+        bail!("property {str} should be in the form key=value");
+    };
+    let key = key.trim();
+    if key.is_empty() {
+        bail!("property {str} has an empty key");
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
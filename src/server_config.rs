@@ -0,0 +1,215 @@
+use crate::cli::{LoaderChoice, NewCommand};
+use crate::commands::add::ModListEntry;
+use crate::memory::parse_memory_size;
+use crate::mod_loader::ModLoader;
+use crate::mod_provider::ModProvider;
+use crate::ContextExt;
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// everything needed to create an instance, so that it can be kept in version control and rebuilt
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    name: Option<String>,
+    dir: Option<PathBuf>,
+    version: Option<String>,
+    loader: Option<ModLoader>,
+    fabric_loader_version: Option<String>,
+    paper_build: Option<u32>,
+    eula: Option<bool>,
+    config_template: Option<PathBuf>,
+    max_memory: Option<String>,
+    #[serde(default)]
+    jvm_args: Vec<String>,
+    #[serde(default)]
+    properties: BTreeMap<String, String>,
+    #[serde(default)]
+    mods: Vec<ConfigMod>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum ConfigMod {
+    // a name, URL or name@version, like a line of add --from-file
+    Name(String),
+    Detailed(DetailedConfigMod),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DetailedConfigMod {
+    name: String,
+    version: Option<String>,
+    provider: Option<ModProvider>,
+}
+
+impl ServerConfig {
+    pub fn load(path: &Path) -> anyhow::Result<ServerConfig> {
+        let contents = fs::read_to_string(path).with_path_context(path)?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("invalid server config {}", path.display()))
+    }
+
+    // arguments given on the command line take precedence over the config
+    pub fn apply(self, command: &mut NewCommand, path: &Path) -> anyhow::Result<()> {
+        // relative paths in the config are relative to the config itself rather than the current directory
+        let base_dir = path.parent().unwrap_or(Path::new(""));
+
+        if command.name.is_none() {
+            command.name = self.name;
+        }
+        if command.dir.is_none() {
+            command.dir = self.dir.map(|dir| base_dir.join(dir));
+        }
+        if command.version.is_none() {
+            command.version = self.version;
+        }
+        if command.loader_choice.is_none() {
+            command.loader_choice = self.loader.map(LoaderChoice::Loader);
+        }
+        if command.fabric_loader_version.is_none() {
+            command.fabric_loader_version = self.fabric_loader_version;
+        }
+        if command.paper_build.is_none() {
+            command.paper_build = self.paper_build;
+        }
+        if command.eula.is_none() {
+            command.eula = self.eula;
+        }
+        if command.config_template.is_none() {
+            command.config_template = self
+                .config_template
+                .map(|config_template| base_dir.join(config_template));
+        }
+        if command.max_memory.is_none() && !command.memory_auto {
+            if let Some(max_memory) = &self.max_memory {
+                command.max_memory = Some(
+                    parse_memory_size(max_memory)
+                        .with_context(|| format!("invalid max_memory in {}", path.display()))?,
+                );
+            }
+        }
+
+        // the config comes first so that the command line can add to it
+        let mut jvm_args = self.jvm_args;
+        jvm_args.append(&mut command.jvm_args);
+        command.jvm_args = jvm_args;
+        let mut properties: Vec<_> = self.properties.into_iter().collect();
+        properties.append(&mut command.properties);
+        command.properties = properties;
+        command.config_mods = self.mods;
+
+        Ok(())
+    }
+}
+
+impl ConfigMod {
+    // the default provider depends on the loader, which may not be known until the instance is created
+    pub fn to_entry(&self, default_provider: ModProvider) -> ModListEntry {
+        match self {
+            ConfigMod::Name(name) => ModListEntry::parse(name, default_provider),
+            ConfigMod::Detailed(config_mod) => ModListEntry::new(
+                &config_mod.name,
+                config_mod.version.clone(),
+                config_mod.provider.unwrap_or(default_provider),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    fn parse_new(args: &[&str]) -> NewCommand {
+        let cli = Cli::try_parse_from(["generate_mcserver", "new"].iter().chain(args)).unwrap();
+        match cli.command {
+            Command::New(command) => *command,
+            _ => unreachable!(),
+        }
+    }
+
+    fn parse_config(json: &str) -> ServerConfig {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn command_line_takes_precedence() {
+        let config = parse_config(
+            r#"{
+                "name": "from-config",
+                "version": "1.20.1",
+                "loader": "fabric",
+                "max_memory": "2G",
+                "jvm_args": ["-Dfrom.config=true"],
+                "properties": { "motd": "from config", "pvp": "false" }
+            }"#,
+        );
+        let mut command = parse_new(&[
+            "from-cli",
+            "--version",
+            "1.21",
+            "--max-memory",
+            "4G",
+            "--jvm-arg=-Dfrom.cli=true",
+            "--property",
+            "motd=from cli",
+        ]);
+        config
+            .apply(&mut command, Path::new("server.json"))
+            .unwrap();
+
+        assert_eq!(command.name.as_deref(), Some("from-cli"));
+        assert_eq!(command.version.as_deref(), Some("1.21"));
+        // in megabytes
+        assert_eq!(command.max_memory, Some(4096));
+        // only given in the config
+        assert_eq!(
+            command.loader_choice,
+            Some(LoaderChoice::Loader(ModLoader::Fabric))
+        );
+        // lists are combined, with the command line last so that it wins
+        assert_eq!(command.jvm_args, ["-Dfrom.config=true", "-Dfrom.cli=true"]);
+        assert_eq!(
+            command.properties,
+            [
+                ("motd".to_owned(), "from config".to_owned()),
+                ("pvp".to_owned(), "false".to_owned()),
+                ("motd".to_owned(), "from cli".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_paths_are_relative_to_the_config() {
+        let config =
+            parse_config(r#"{ "dir": "servers/survival", "config_template": "template" }"#);
+        let mut command = parse_new(&["--config", "configs/server.json"]);
+        config
+            .apply(&mut command, Path::new("configs/server.json"))
+            .unwrap();
+
+        assert_eq!(command.dir, Some(PathBuf::from("configs/servers/survival")));
+        assert_eq!(
+            command.config_template,
+            Some(PathBuf::from("configs/template"))
+        );
+    }
+
+    #[test]
+    fn command_line_paths_are_kept() {
+        let config = parse_config(r#"{ "dir": "servers/survival" }"#);
+        let mut command = parse_new(&["--config", "configs/server.json", "--dir", "elsewhere"]);
+        config
+            .apply(&mut command, Path::new("configs/server.json"))
+            .unwrap();
+
+        assert_eq!(command.dir, Some(PathBuf::from("elsewhere")));
+    }
+}
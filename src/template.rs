@@ -0,0 +1,94 @@
+use crate::output::verbose;
+use crate::ContextExt;
+use anyhow::bail;
+use std::fs;
+use std::path::PathBuf;
+
+// binary files could contain "${" by chance, so only these are substituted in
+const TEXT_EXTENSIONS: &[&str] = &["properties", "yml", "yaml", "json", "txt", "cfg"];
+
+pub fn parse_define(str: &str) -> anyhow::Result<(String, String)> {
+    let Some((key, value)) = str.split_once('=') else {
+        bail!("define {str} should be in the form KEY=VALUE");
+    };
+    if key.is_empty() || !key.chars().all(is_key_char) {
+        bail!("define {str} has an invalid key, use only letters, digits, '_', '-' and '.'");
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+// replaces ${KEY} in the text files copied from the config template
+pub fn substitute_defines(
+    files: &[PathBuf],
+    defines: &[(String, String)],
+    strict: bool,
+) -> anyhow::Result<()> {
+    let mut unmatched = Vec::new();
+    for file in files {
+        let is_text = file
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| TEXT_EXTENSIONS.contains(&&*ext.to_lowercase()));
+        if !is_text {
+            continue;
+        }
+        let contents = fs::read(file).with_path_context(file)?;
+        let Ok(contents) = String::from_utf8(contents) else {
+            eprintln!(
+                "warning: {} is not valid UTF-8, not substituting defines in it",
+                file.display()
+            );
+            continue;
+        };
+
+        let (result, file_unmatched) = substitute(&contents, defines);
+        for key in file_unmatched {
+            unmatched.push(format!("${{{key}}} in {}", file.display()));
+        }
+        if result != contents {
+            verbose!("substituting defines in {}", file.display());
+            fs::write(file, result).with_path_context(file)?;
+        }
+    }
+
+    if strict && !unmatched.is_empty() {
+        bail!(
+            "the config template has placeholders with no --define: {}",
+            unmatched.join(", ")
+        );
+    }
+    Ok(())
+}
+
+// returns the substituted text and the keys of placeholders that were left alone
+fn substitute<'a>(contents: &'a str, defines: &[(String, String)]) -> (String, Vec<&'a str>) {
+    let mut result = String::with_capacity(contents.len());
+    let mut unmatched = Vec::new();
+    let mut rest = contents;
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let key_len = after.find(|c| !is_key_char(c)).unwrap_or(after.len());
+        let key = &after[..key_len];
+        if key.is_empty() || !after[key_len..].starts_with('}') {
+            // not a placeholder, keep the "${" and carry on after it
+            result.push_str("${");
+            rest = after;
+            continue;
+        }
+        match defines.iter().rev().find(|(define, _)| define == key) {
+            Some((_, value)) => result.push_str(value),
+            None => {
+                result.push_str(&rest[start..start + 2 + key_len + 1]);
+                unmatched.push(key);
+            }
+        }
+        rest = &after[key_len + 1..];
+    }
+    result.push_str(rest);
+    (result, unmatched)
+}